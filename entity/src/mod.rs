@@ -2,4 +2,7 @@
 
 pub mod prelude;
 
+pub mod blob;
+pub mod multipart_upload;
+pub mod multipart_upload_part;
 pub mod object;
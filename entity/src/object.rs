@@ -14,6 +14,10 @@ pub struct Model {
     pub internal_filename: String,
     pub encoded_filename: Option<String>,
     pub filename: Option<String>,
+    pub updated_at: Option<DateTimeWithTimeZone>,
+    pub created_at: Option<DateTimeWithTimeZone>,
+    pub e_tag: Option<String>,
+    pub headers: Option<Json>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
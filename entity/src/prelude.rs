@@ -1,3 +1,5 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.13
 
-pub use super::object::Entity as Object;
+pub use super::{
+    blob::Entity as Blob, multipart_upload::Entity as MultipartUpload, multipart_upload_part::Entity as MultipartUploadPart, object::Entity as Object,
+};
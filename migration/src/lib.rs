@@ -1,10 +1,18 @@
 pub use sea_orm_migration::prelude::*;
 
+// 公開済みのマイグレーションは書き換えない
+#[allow(clippy::question_mark)]
 mod m20250702_134901_create_objects_table;
 mod m20250705_083629_add_internal_filename_column_to_object_table;
 mod m20250712_185118_add_encoded_filename_column_to_object_table;
 mod m20250811_061518_drop_filename_column;
 mod m20250811_064437_add_nullable_filename_column;
+mod m20261018_023512_create_multipart_upload_table;
+mod m20261018_051204_create_blob_table;
+mod m20261018_061127_add_updated_at_column_to_object_table;
+mod m20261018_063409_add_created_at_column_to_object_table;
+mod m20261018_071540_add_e_tag_columns;
+mod m20261018_083012_add_headers_columns;
 
 pub struct Migrator;
 
@@ -17,6 +25,12 @@ impl MigratorTrait for Migrator {
             Box::new(m20250712_185118_add_encoded_filename_column_to_object_table::Migration),
             Box::new(m20250811_061518_drop_filename_column::Migration),
             Box::new(m20250811_064437_add_nullable_filename_column::Migration),
+            Box::new(m20261018_023512_create_multipart_upload_table::Migration),
+            Box::new(m20261018_051204_create_blob_table::Migration),
+            Box::new(m20261018_061127_add_updated_at_column_to_object_table::Migration),
+            Box::new(m20261018_063409_add_created_at_column_to_object_table::Migration),
+            Box::new(m20261018_071540_add_e_tag_columns::Migration),
+            Box::new(m20261018_083012_add_headers_columns::Migration),
         ]
     }
 }
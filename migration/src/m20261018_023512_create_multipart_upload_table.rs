@@ -0,0 +1,89 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // create the multipart_upload table
+        manager
+            .create_table(
+                Table::create()
+                    .table(MultipartUpload::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(MultipartUpload::UploadId).string().not_null().primary_key())
+                    .col(ColumnDef::new(MultipartUpload::Path).string().not_null())
+                    .col(ColumnDef::new(MultipartUpload::Filename).string().null())
+                    .col(ColumnDef::new(MultipartUpload::EncodedFilename).string().null())
+                    .col(ColumnDef::new(MultipartUpload::MimeType).string().not_null())
+                    .col(ColumnDef::new(MultipartUpload::CreatedAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(MultipartUpload::LastUploadAt).timestamp_with_time_zone().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // create the multipart_upload_part table
+        manager
+            .create_table(
+                Table::create()
+                    .table(MultipartUploadPart::Table)
+                    .if_not_exists()
+                    .col(pk_auto(MultipartUploadPart::Id))
+                    .col(ColumnDef::new(MultipartUploadPart::UploadId).string().not_null())
+                    .col(ColumnDef::new(MultipartUploadPart::PartNumber).integer().not_null())
+                    .col(ColumnDef::new(MultipartUploadPart::ContentSize).big_integer().not_null())
+                    .col(ColumnDef::new(MultipartUploadPart::UpdatedAt).timestamp_with_time_zone().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_multipart_upload_part_upload_id")
+                            .from(MultipartUploadPart::Table, MultipartUploadPart::UploadId)
+                            .to(MultipartUpload::Table, MultipartUpload::UploadId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // create the unique index on the upload_id and part_number columns
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_multipart_upload_part_upload_id_part_number")
+                    .table(MultipartUploadPart::Table)
+                    .col(MultipartUploadPart::UploadId)
+                    .col(MultipartUploadPart::PartNumber)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(MultipartUploadPart::Table).to_owned()).await?;
+
+        manager.drop_table(Table::drop().table(MultipartUpload::Table).to_owned()).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum MultipartUpload {
+    Table,
+    UploadId,
+    Path,
+    Filename,
+    EncodedFilename,
+    MimeType,
+    CreatedAt,
+    LastUploadAt,
+}
+
+#[derive(DeriveIden)]
+enum MultipartUploadPart {
+    Table,
+    Id,
+    UploadId,
+    PartNumber,
+    ContentSize,
+    UpdatedAt,
+}
@@ -0,0 +1,36 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // create the blob table
+        manager
+            .create_table(
+                Table::create()
+                    .table(Blob::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Blob::Hash).string().not_null().primary_key())
+                    .col(ColumnDef::new(Blob::ContentSize).big_integer().not_null())
+                    .col(ColumnDef::new(Blob::RefCount).big_integer().not_null())
+                    .col(ColumnDef::new(Blob::CreatedAt).timestamp_with_time_zone().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(Blob::Table).to_owned()).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Blob {
+    Table,
+    Hash,
+    ContentSize,
+    RefCount,
+    CreatedAt,
+}
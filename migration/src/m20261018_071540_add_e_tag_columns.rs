@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Object::Table)
+                    .add_column(ColumnDef::new(Object::ETag).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MultipartUploadPart::Table)
+                    .add_column(ColumnDef::new(MultipartUploadPart::ETag).string().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MultipartUploadPart::Table)
+                    .drop_column(MultipartUploadPart::ETag)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(Table::alter().table(Object::Table).drop_column(Object::ETag).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Object {
+    Table,
+    ETag,
+}
+
+#[derive(DeriveIden)]
+enum MultipartUploadPart {
+    Table,
+    ETag,
+}
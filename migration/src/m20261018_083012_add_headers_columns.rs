@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Object::Table)
+                    .add_column(ColumnDef::new(Object::Headers).json().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MultipartUpload::Table)
+                    .add_column(ColumnDef::new(MultipartUpload::Headers).json().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MultipartUpload::Table)
+                    .drop_column(MultipartUpload::Headers)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(Table::alter().table(Object::Table).drop_column(Object::Headers).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Object {
+    Table,
+    Headers,
+}
+
+#[derive(DeriveIden)]
+enum MultipartUpload {
+    Table,
+    Headers,
+}
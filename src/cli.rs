@@ -12,12 +12,25 @@ struct Args {
 
 #[derive(Subcommand, Debug)]
 pub enum MigrationCommand {
-    /// Migrate the objects from ofuton v1
+    /// Migrate the objects from ofuton v1, backfill object timestamps, or convert the bucket to the deduplicated layout
     Migrate {
-        #[arg(value_name = "OLD_DIR_PATH", help = "Path to the old ofuton v1 objects root directory")]
-        old_dir: String,
+        #[arg(
+            value_name = "OLD_DIR_PATH",
+            help = "Path to the old ofuton v1 objects root directory",
+            required_unless_present_any = ["dedupe", "timestamps"]
+        )]
+        old_dir: Option<String>,
+
+        #[arg(long, help = "Convert the objects in the bucket to the content-addressed deduplicated layout")]
+        dedupe: bool,
+
+        #[arg(long, help = "Backfill missing object timestamps from the modification time of the stored files")]
+        timestamps: bool,
     },
 
+    /// Move the object files into the directory layout set by `bucket.shard_depth`
+    Reshard,
+
     /// Validate the objects
     // Validate {
     //     #[arg(value_name = "DIR_PATH", help = "Path to the directory to validate")]
@@ -37,8 +50,11 @@ pub fn handle() -> Option<MigrationCommand> {
 
 pub async fn execute(command: MigrationCommand) {
     match command {
-        MigrationCommand::Migrate { old_dir } => {
-            command::migrate::execute(old_dir).await;
+        MigrationCommand::Migrate { old_dir, dedupe, timestamps } => {
+            command::migrate::execute(old_dir, dedupe, timestamps).await;
+        }
+        MigrationCommand::Reshard => {
+            command::reshard::execute().await;
         }
         MigrationCommand::Import { metadata_path } => {
             command::import::execute(metadata_path).await;
@@ -1,3 +1,4 @@
 pub mod import;
 pub mod migrate;
+pub mod reshard;
 pub mod validate;
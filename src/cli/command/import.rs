@@ -20,8 +20,8 @@ pub async fn execute(metadata_path: String) {
     reader_builder.has_headers(false);
 
     let reader = reader_builder.from_path(metadata_path);
-    if reader.is_err() {
-        tracing::error!("Failed to read metadata file: {}", reader.unwrap_err());
+    if let Err(e) = &reader {
+        tracing::error!("Failed to read metadata file: {}", e);
         return;
     }
 
@@ -29,8 +29,8 @@ pub async fn execute(metadata_path: String) {
     let mut drive_files = Vec::new();
 
     for result in records.deserialize() {
-        if result.is_err() {
-            tracing::error!("Failed to deserialize record: {}", result.unwrap_err());
+        if let Err(e) = &result {
+            tracing::error!("Failed to deserialize record: {}", e);
             continue;
         }
 
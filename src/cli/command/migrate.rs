@@ -1,11 +1,12 @@
-use crate::{cli::utils, config, database};
+use crate::{cli::utils, config, database, storage};
 use async_recursion::async_recursion;
+use chrono::{DateTime, Utc};
 use dialoguer::Confirm;
 use entity;
 use indicatif::ProgressBar;
 use mime_guess;
-use sea_orm::{ActiveValue::Set, EntityTrait};
-use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+use sea_orm::{ActiveValue::Set, ColumnTrait, Condition, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect};
+use std::path::{MAIN_SEPARATOR, PathBuf};
 use tokio::fs;
 
 #[derive(Debug)]
@@ -15,19 +16,35 @@ pub struct MigrateObject {
     pub model: entity::object::ActiveModel,
 }
 
-pub async fn execute(old_dir: String) {
+pub async fn execute(old_dir: Option<String>, dedupe: bool, timestamps: bool) {
+    if let Some(old_dir) = old_dir &&
+        !migrate_from_v1(old_dir).await
+    {
+        return;
+    }
+
+    if timestamps {
+        backfill_timestamps().await;
+    }
+
+    if dedupe {
+        convert_to_dedupe().await;
+    }
+}
+
+async fn migrate_from_v1(old_dir: String) -> bool {
     tracing::info!("Calcurating files to migrate from old directory: {}", old_dir);
     let total_files = match count_files_recursive(&old_dir).await {
         Ok(count) => count,
         Err(e) => {
             tracing::error!("Failed to count files: {}", e);
-            return;
+            return false;
         }
     };
 
     if total_files == 0 {
         tracing::info!("No files to migrate.");
-        return;
+        return false;
     }
     tracing::info!("Found {} files to migrate.", total_files);
 
@@ -35,20 +52,142 @@ pub async fn execute(old_dir: String) {
 
     if !confirmation {
         tracing::info!("Migration cancelled.");
-        return;
+        return false;
     }
 
     let pb = utils::create_progress_bar(total_files);
     let mut items: Vec<MigrateObject> = Vec::new();
     if let Err(e) = migrate_objects_recursive(&old_dir, &old_dir, &mut items, &pb).await {
         tracing::error!("Failed to migrate objects from old directory: {}", e);
-        return;
+        return false;
     }
 
     pb.finish();
     tracing::info!(
         "Migration completed successfully. If necessary, run the `import` command. (The `import` command imports accurate file information from Misskey)"
     );
+
+    if config::CONFIG.bucket.dedupe {
+        tracing::warn!("`bucket.dedupe` is enabled. Run the `migrate --dedupe` command to deduplicate the migrated objects.");
+    }
+
+    true
+}
+
+/// バケット内のオブジェクトを内容アドレス方式のレイアウトにその場で変換する
+async fn convert_to_dedupe() {
+    let total_objects = match entity::object::Entity::find().count(database::get_db()).await {
+        Ok(count) => count,
+        Err(e) => {
+            tracing::error!("Failed to count objects: {}", e);
+            return;
+        }
+    };
+
+    if total_objects == 0 {
+        tracing::info!("No objects to convert.");
+        return;
+    }
+    tracing::info!("Found {} objects to convert to the deduplicated layout.", total_objects);
+    tracing::warn!("Please stop the server before converting the bucket.");
+
+    let confirmation = Confirm::new().with_prompt("Continue?").interact().unwrap();
+
+    if !confirmation {
+        tracing::info!("Conversion cancelled.");
+        return;
+    }
+
+    let pb = utils::create_progress_bar(total_objects);
+    let converted = process_objects(
+        Condition::all(),
+        &pb,
+        |object| async move { storage::convert_object_to_blob(&object).await },
+    )
+    .await;
+    if let Err(e) = converted {
+        tracing::error!("Failed to convert objects: {}", e);
+        return;
+    }
+
+    pb.finish();
+    tracing::info!(
+        "Converted {} objects. Set `bucket.dedupe = true` in config.toml to store new objects in this layout.",
+        converted.unwrap()
+    );
+}
+
+/// 作成日時・更新日時が記録されていないオブジェクトに、ファイルの更新日時を設定する
+async fn backfill_timestamps() {
+    let condition = Condition::any()
+        .add(entity::object::Column::CreatedAt.is_null())
+        .add(entity::object::Column::UpdatedAt.is_null());
+
+    let total_objects = match entity::object::Entity::find().filter(condition.clone()).count(database::get_db()).await {
+        Ok(count) => count,
+        Err(e) => {
+            tracing::error!("Failed to count objects: {}", e);
+            return;
+        }
+    };
+
+    if total_objects == 0 {
+        tracing::info!("No objects to backfill timestamps.");
+        return;
+    }
+    tracing::info!("Found {} objects without timestamps.", total_objects);
+
+    let pb = utils::create_progress_bar(total_objects);
+    let backfilled = process_objects(condition, &pb, |object| async move { storage::backfill_object_timestamps(&object).await }).await;
+    if let Err(e) = backfilled {
+        tracing::error!("Failed to backfill timestamps: {}", e);
+        return;
+    }
+
+    pb.finish();
+    tracing::info!(
+        "Backfilled timestamps of {} objects from the file modification time.",
+        backfilled.unwrap()
+    );
+}
+
+/// 条件に一致するオブジェクトをidの順に少しずつ取得して処理し、`f`がtrueを返した数を返す
+async fn process_objects<F, Fut>(condition: Condition, pb: &ProgressBar, f: F) -> Result<u64, anyhow::Error>
+where
+    F: Fn(entity::object::Model) -> Fut,
+    Fut: Future<Output = Result<bool, anyhow::Error>>,
+{
+    let chunk_size = 100;
+    let mut cursor = 0;
+    let mut processed = 0;
+
+    loop {
+        let objects = entity::object::Entity::find()
+            .filter(condition.clone())
+            .filter(entity::object::Column::Id.gt(cursor))
+            .order_by_asc(entity::object::Column::Id)
+            .limit(chunk_size)
+            .all(database::get_db())
+            .await?;
+
+        if objects.is_empty() {
+            break;
+        }
+
+        for object in objects {
+            cursor = object.id;
+            let path = object.path.clone();
+            match f(object).await {
+                Ok(true) => processed += 1,
+                Ok(false) => {}
+                Err(e) => return Err(anyhow::anyhow!("{}: {}", path, e)),
+            }
+
+            pb.inc(1);
+        }
+    }
+
+    Ok(processed)
 }
 
 #[async_recursion]
@@ -83,6 +222,8 @@ async fn migrate_objects_recursive(base_dir: &str, current_dir: &str, items: &mu
         let relative_path_str = format!("/{}", relative_path.to_string_lossy().to_string().replace(MAIN_SEPARATOR, "/"));
 
         let mime = mime_guess::from_path(&path).first_or_octet_stream().to_string();
+        let file_metadata = entry.metadata().await?;
+        let modified_time = DateTime::<Utc>::from(file_metadata.modified()?).fixed_offset();
 
         // let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string();
         // let normalized_filename = utils::FILENAME_NORMALIZE_REGEX.replace_all(&filename, "_").to_string();
@@ -92,9 +233,11 @@ async fn migrate_objects_recursive(base_dir: &str, current_dir: &str, items: &mu
         let object = entity::object::ActiveModel {
             path: Set(relative_path_str),
             // filename: Set(normalized_filename),
-            content_size: Set(entry.metadata().await?.len() as i64),
+            content_size: Set(file_metadata.len() as i64),
             mime_type: Set(mime),
             internal_filename: Set(internal_filename.clone()),
+            created_at: Set(Some(modified_time)),
+            updated_at: Set(Some(modified_time)),
             ..Default::default()
         };
 
@@ -127,8 +270,8 @@ async fn migrate_objects(items: &mut Vec<MigrateObject>, pb: &ProgressBar) -> Re
     entity::object::Entity::insert_many(models).exec(database::get_db()).await?;
 
     for item in &mut *items {
-        let dist = Path::new(&config::CONFIG.bucket.path).join(&item.internal_filename);
-        fs::rename(&item.path, dist).await?;
+        let path = item.model.path.clone().unwrap();
+        storage::import_object_file(&path, &item.path, item.internal_filename.clone()).await?;
     }
 
     pb.inc(items.len() as u64);
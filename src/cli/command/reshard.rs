@@ -0,0 +1,42 @@
+use crate::{cli::utils, config, storage};
+use dialoguer::Confirm;
+
+pub async fn execute() {
+    let total_files = match storage::count_object_files().await {
+        Ok(count) => count,
+        Err(e) => {
+            tracing::error!("Failed to count files: {}", e);
+            return;
+        }
+    };
+
+    if total_files == 0 {
+        tracing::info!("No files to reshard.");
+        return;
+    }
+    tracing::info!(
+        "Found {} files to reshard with `bucket.shard_depth = {}`.",
+        total_files,
+        config::CONFIG.bucket.shard_depth
+    );
+    tracing::info!("The server can keep running while resharding, but make sure it has been restarted with the same `bucket.shard_depth`.");
+
+    let confirmation = Confirm::new().with_prompt("Continue?").interact().unwrap();
+
+    if !confirmation {
+        tracing::info!("Reshard cancelled.");
+        return;
+    }
+
+    let pb = utils::create_progress_bar(total_files);
+    let moved = match storage::reshard_object_files(|| pb.inc(1)).await {
+        Ok(moved) => moved,
+        Err(e) => {
+            tracing::error!("Failed to reshard the bucket: {}", e);
+            return;
+        }
+    };
+
+    pb.finish();
+    tracing::info!("Reshard completed successfully. Moved {} files.", moved);
+}
@@ -8,6 +8,25 @@ use std::{fs, path::Path, process};
 pub struct CFGServer {
     pub host: String,
     pub port: u16,
+    pub base_domain: String,
+    pub shutdown_timeout_seconds: u64,
+    pub tls: CFGServerTls,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CFGServerTls {
+    pub enabled: bool,
+    pub cert_path: String,
+    pub key_path: String,
+    pub http2: bool,
+    pub reload_interval_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CFGMetrics {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -36,6 +55,29 @@ pub struct CFGBucket {
     pub path: String,
     pub max_upload_size_mb: u64,
     pub request_expiration_seconds: i64,
+    pub dedupe: bool,
+    pub shard_depth: u8,
+    /// 署名付きリクエストでのみ読み取りを許可するパス (`bucket/key`) の接頭辞
+    pub private_prefixes: Vec<String>,
+}
+
+/// `[[buckets]]`で宣言するバケット
+/// 省略した項目は`[bucket]`の値を使用する
+#[derive(Debug, Deserialize, Clone)]
+pub struct CFGNamedBucket {
+    pub name: String,
+    pub path: Option<String>,
+    pub max_upload_size_mb: Option<u64>,
+    pub request_expiration_seconds: Option<i64>,
+}
+
+/// バケットごとの設定
+#[derive(Debug, Clone)]
+pub struct BucketConfig {
+    pub name: String,
+    pub path: String,
+    pub max_upload_size_mb: u64,
+    pub request_expiration_seconds: i64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -44,6 +86,25 @@ pub struct CFGAccount {
     pub secret_key: String,
 }
 
+/// アクセスキーに許可する操作
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CFGPermission {
+    Read,
+    Write,
+    Delete,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CFGCredential {
+    pub access_key: String,
+    pub secret_key: String,
+    pub permissions: Vec<CFGPermission>,
+    /// 操作を許可するパス (`bucket/key`) の接頭辞。空の場合は全てのパスを許可する
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct CFGSentry {
     pub dsn: String,
@@ -59,8 +120,13 @@ pub struct AppConfig {
     pub server: CFGServer,
     pub database: CFGDatabase,
     pub bucket: CFGBucket,
+    #[serde(default)]
+    pub buckets: Vec<CFGNamedBucket>,
     pub account: CFGAccount,
+    #[serde(default)]
+    pub credentials: Vec<CFGCredential>,
     pub sentry: CFGSentry,
+    pub metrics: CFGMetrics,
     pub debug: Option<CFGDebug>,
 }
 
@@ -81,6 +147,48 @@ impl AppConfig {
 
         config.try_deserialize::<AppConfig>()
     }
+
+    /// バケットの設定を返す
+    /// `[[buckets]]`が宣言されていない場合は、以前と同様に全てのバケット名を`[bucket]`の設定で受け付ける
+    pub fn find_bucket(&self, name: &str) -> Option<BucketConfig> {
+        if name.is_empty() {
+            return None;
+        }
+
+        if self.buckets.is_empty() {
+            return Some(BucketConfig {
+                name: name.to_string(),
+                path: self.bucket.path.clone(),
+                max_upload_size_mb: self.bucket.max_upload_size_mb,
+                request_expiration_seconds: self.bucket.request_expiration_seconds,
+            });
+        }
+
+        self.buckets.iter().find(|bucket| bucket.name == name).map(|bucket| BucketConfig {
+            name: bucket.name.clone(),
+            path: bucket.path.clone().unwrap_or(self.bucket.path.clone()),
+            max_upload_size_mb: bucket.max_upload_size_mb.unwrap_or(self.bucket.max_upload_size_mb),
+            request_expiration_seconds: bucket.request_expiration_seconds.unwrap_or(self.bucket.request_expiration_seconds),
+        })
+    }
+
+    /// 宣言されているバケットの設定を返す
+    pub fn declared_buckets(&self) -> Vec<BucketConfig> {
+        self.buckets.iter().filter_map(|bucket| self.find_bucket(&bucket.name)).collect()
+    }
+
+    /// オブジェクトのファイルを保存する全てのディレクトリを返す
+    /// `[bucket]`のディレクトリは、バケットの宣言前に保存されたオブジェクトのために常に含める
+    pub fn storage_paths(&self) -> Vec<String> {
+        let mut paths = vec![self.bucket.path.clone()];
+        for bucket in self.declared_buckets() {
+            if !paths.contains(&bucket.path) {
+                paths.push(bucket.path);
+            }
+        }
+
+        paths
+    }
 }
 
 pub static CONFIG: Lazy<AppConfig> = Lazy::new(|| AppConfig::new().expect("Failed to initialize application configuration"));
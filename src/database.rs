@@ -45,3 +45,12 @@ pub async fn initialize() -> Result<(), sea_orm::DbErr> {
 pub fn get_db() -> &'static DatabaseConnection {
     DB.get().expect("Database has not been initialized")
 }
+
+/// 終了前にデータベースの接続を閉じ、書き込みを確定させる
+pub async fn close() {
+    if let Some(db) = DB.get() &&
+        let Err(err) = db.close_by_ref().await
+    {
+        tracing::error!("Failed to close the database connection: {}", err);
+    }
+}
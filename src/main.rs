@@ -25,15 +25,17 @@ fn main() {
     tracing_subscriber::fmt().with_env_filter(filter).init();
 
     // Sentry setup
+    // ガードを破棄するとクライアントが閉じられるため、終了するまで保持する
+    let mut sentry_guard = None;
     if !conf.sentry.dsn.is_empty() &&
         let Ok(dsn) = sentry::IntoDsn::into_dsn(conf.sentry.dsn)
     {
         tracing::info!("Sentry logging is enabled");
-        let _guard = sentry::init(sentry::ClientOptions {
+        sentry_guard = Some(sentry::init(sentry::ClientOptions {
             dsn,
             release: sentry::release_name!(),
             ..Default::default()
-        });
+        }));
     }
 
     // Handle argments
@@ -47,6 +49,9 @@ fn main() {
         .block_on(async {
             run(command).await;
         });
+
+    // 送信待ちのイベントを送信してから終了する
+    drop(sentry_guard);
 }
 
 async fn run(command: Option<cli::MigrationCommand>) {
@@ -58,4 +63,6 @@ async fn run(command: Option<cli::MigrationCommand>) {
     } else {
         server::listen().await;
     }
+
+    database::close().await;
 }
@@ -1,22 +1,27 @@
 use crate::config;
 use axum::{
-    Router,
-    extract::DefaultBodyLimit,
-    http::StatusCode,
+    Router, ServiceExt,
     response::{IntoResponse, Response},
     routing,
 };
-use std::{sync::Arc, time::Duration};
+use http_body_util::LengthLimitError;
+use std::time::Duration;
 use tokio::net::TcpListener;
+use tower::{Layer, util::MapRequestLayer};
 use tower_http::{
     request_id::{MakeRequestUuid, SetRequestIdLayer},
     trace::TraceLayer,
 };
 use tracing::Span;
-use uuid::Uuid;
 
 mod api;
+pub mod error;
+mod metrics;
 mod middleware;
+mod operation;
+mod payload;
+mod shutdown;
+mod tls;
 mod utils;
 
 // Error handling
@@ -32,13 +37,16 @@ where E: Into<anyhow::Error>
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let request_id = Uuid::new_v4();
-        tracing::error!(request_id = %request_id, "{}", self.0);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Internal Server Error (RequestID: {request_id})"),
-        )
-            .into_response()
+        if let Some(error) = self.0.downcast_ref::<error::S3Error>() {
+            return error.clone().into_response();
+        }
+
+        if self.0.chain().any(|e| e.is::<LengthLimitError>()) {
+            return error::S3Error::EntityTooLarge.into_response();
+        }
+
+        tracing::error!("{:#}", self.0);
+        error::S3Error::InternalError.into_response()
     }
 }
 
@@ -47,10 +55,7 @@ pub type AppResult<T> = Result<T, AppError>;
 // Server setup
 pub async fn listen() {
     let conf = config::CONFIG.clone();
-    let verify_signatures = middleware::signature::SignatureVerificationState {
-        key: Arc::new(conf.account.access_key.clone()),
-        secret: Arc::new(conf.account.secret_key.clone()),
-    };
+    let verify_signatures = middleware::signature::SignatureVerificationState::new(&conf);
 
     let write_routes = Router::new()
         .route(
@@ -59,21 +64,58 @@ pub async fn listen() {
                 .put(api::object::write::write_handler)
                 .delete(api::object::write::write_handler),
         )
-        .route_layer(DefaultBodyLimit::max((conf.bucket.max_upload_size_mb * 1024 * 1024).try_into().unwrap()))
         .route_layer(axum::middleware::from_fn(middleware::multipart::multipart_state_manager))
         .route_layer(axum::middleware::from_fn_with_state(
-            verify_signatures,
+            verify_signatures.clone(),
             middleware::signature::signature_verification,
-        ));
+        ))
+        .route_layer(axum::middleware::from_fn(middleware::bucket::bucket_resolver));
 
-    let app = Router::new()
-        .route("/", api::r#static::index())
-        .route("/robots.txt", api::r#static::robots_txt())
+    let bucket_routes = Router::new()
+        .route(
+            "/{bucket}",
+            routing::get(api::bucket::read::read_handler)
+                .head(api::bucket::read::head_handler)
+                .post(api::bucket::write::write_handler),
+        )
+        .route(
+            "/{bucket}/",
+            routing::get(api::bucket::read::read_handler)
+                .head(api::bucket::read::head_handler)
+                .post(api::bucket::write::write_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            verify_signatures.clone(),
+            middleware::signature::signature_verification,
+        ))
+        .route_layer(axum::middleware::from_fn(middleware::bucket::bucket_resolver));
+
+    let read_routes = Router::new()
         .route(
             "/{bucket}/{*object}",
             routing::get(api::object::read::read_handler).head(api::object::read::read_handler),
         )
+        .route_layer(axum::middleware::from_fn_with_state(
+            verify_signatures.clone(),
+            middleware::signature::optional_signature_verification,
+        ))
+        .route_layer(axum::middleware::from_fn(middleware::bucket::bucket_resolver));
+
+    // 署名付きリクエストの場合はListBuckets、それ以外はインデックスページを返す
+    let service_routes = Router::new()
+        .route("/", routing::get(api::service::read_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            verify_signatures,
+            middleware::signature::optional_signature_verification,
+        ));
+
+    let app = Router::new()
+        .merge(service_routes)
+        .route("/robots.txt", api::r#static::robots_txt())
+        .merge(read_routes)
         .merge(write_routes)
+        .merge(bucket_routes)
+        .layer(axum::middleware::from_fn(middleware::error::error_renderer))
         .layer(axum::middleware::from_fn(middleware::logger::request_logger))
         .layer(
             TraceLayer::new_for_http().on_response(|response: &Response, latency: Duration, _: &Span| {
@@ -91,17 +133,55 @@ pub async fn listen() {
         )
         .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
+    // 操作の判別にマッチしたルートを使うため、Routerのレイヤーとして適用する
+    let app = if conf.metrics.enabled {
+        metrics::spawn_listener(&conf.metrics);
+        app.layer(axum::middleware::from_fn(metrics::request_metrics))
+    } else {
+        app
+    };
+
+    // バケット名をHostから解決してパスを書き換えるため、ルーティングより前に適用する
+    let app = MapRequestLayer::new(middleware::virtual_host::virtual_host_resolver).layer(app);
+
     let addr = format!("{}:{}", conf.server.host, conf.server.port);
-    let listener = TcpListener::bind(&addr).await;
+    let listener = TcpListener::bind(&addr).await.and_then(|listener| listener.into_std());
     if let Err(err) = listener {
         tracing::error!("Failed to bind to {}: {}", addr, err);
         return;
     }
 
-    tracing::info!("Server listening on http://{}", addr);
+    let listener = listener.unwrap();
+    let handle = axum_server::Handle::new();
+    shutdown::spawn_watcher(handle.clone(), Duration::from_secs(conf.server.shutdown_timeout_seconds));
+
+    let server = if conf.server.tls.enabled {
+        let tls_config = tls::load_config(&conf.server.tls);
+        if let Err(err) = tls_config {
+            tracing::error!("Failed to load the TLS certificate: {:#}", err);
+            return;
+        }
+
+        let tls_config = tls_config.unwrap();
+        tls::spawn_reloader(tls_config.clone(), conf.server.tls.clone());
+
+        tracing::info!("Server listening on https://{}", addr);
+        match axum_server::from_tcp_rustls(listener, tls_config) {
+            Ok(server) => server.handle(handle).serve(app.into_make_service()).await,
+            Err(err) => Err(err),
+        }
+    } else {
+        tracing::info!("Server listening on http://{}", addr);
+        match axum_server::from_tcp(listener) {
+            Ok(server) => server.handle(handle).serve(app.into_make_service()).await,
+            Err(err) => Err(err),
+        }
+    };
 
-    let server = axum::serve(listener.unwrap(), app).await;
     if let Err(err) = server {
         tracing::error!("Server error: {}", err);
+        return;
     }
+
+    tracing::info!("Server has been shut down");
 }
@@ -1,2 +1,4 @@
+pub mod bucket;
 pub mod object;
+pub mod service;
 pub mod r#static;
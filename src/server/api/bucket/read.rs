@@ -0,0 +1,366 @@
+use crate::{
+    config::CFGPermission,
+    server::{
+        AppResult,
+        error::S3Error,
+        middleware::signature::SignatureContext,
+        utils::{S3_XML_NAMESPACE, decode_object_key, encode_object_key, format_e_tag, xml_response},
+    },
+    storage,
+};
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::SecondsFormat;
+use serde::{Deserialize, Serialize};
+
+const MAX_KEYS_LIMIT: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct ReqParams {
+    #[serde(rename = "list-type")]
+    list_type: Option<u8>,
+    prefix: Option<String>,
+    delimiter: Option<String>,
+    #[serde(rename = "max-keys")]
+    max_keys: Option<usize>,
+    #[serde(rename = "start-after")]
+    start_after: Option<String>,
+    #[serde(rename = "continuation-token")]
+    continuation_token: Option<String>,
+    marker: Option<String>,
+    #[serde(rename = "encoding-type")]
+    encoding_type: Option<String>,
+    uploads: Option<String>,
+    #[serde(rename = "key-marker")]
+    key_marker: Option<String>,
+    #[serde(rename = "upload-id-marker")]
+    upload_id_marker: Option<String>,
+    #[serde(rename = "max-uploads")]
+    max_uploads: Option<usize>,
+}
+
+// S3 API Response Structures
+#[derive(Debug, Serialize)]
+pub struct S3Object {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "LastModified", skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    #[serde(rename = "ETag")]
+    pub e_tag: String,
+    #[serde(rename = "Size")]
+    pub size: i64,
+    #[serde(rename = "StorageClass")]
+    pub storage_class: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct S3CommonPrefix {
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListBucketResult")]
+pub struct S3ListBucketResultV2 {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
+    #[serde(rename = "Delimiter", skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    #[serde(rename = "StartAfter", skip_serializing_if = "Option::is_none")]
+    pub start_after: Option<String>,
+    #[serde(rename = "ContinuationToken", skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<String>,
+    #[serde(rename = "NextContinuationToken", skip_serializing_if = "Option::is_none")]
+    pub next_continuation_token: Option<String>,
+    #[serde(rename = "EncodingType", skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<String>,
+    #[serde(rename = "MaxKeys")]
+    pub max_keys: usize,
+    #[serde(rename = "KeyCount")]
+    pub key_count: usize,
+    #[serde(rename = "IsTruncated")]
+    pub is_truncated: bool,
+    #[serde(rename = "Contents")]
+    pub contents: Vec<S3Object>,
+    #[serde(rename = "CommonPrefixes")]
+    pub common_prefixes: Vec<S3CommonPrefix>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListBucketResult")]
+pub struct S3ListBucketResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
+    #[serde(rename = "Delimiter", skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    #[serde(rename = "Marker")]
+    pub marker: String,
+    #[serde(rename = "NextMarker", skip_serializing_if = "Option::is_none")]
+    pub next_marker: Option<String>,
+    #[serde(rename = "EncodingType", skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<String>,
+    #[serde(rename = "MaxKeys")]
+    pub max_keys: usize,
+    #[serde(rename = "IsTruncated")]
+    pub is_truncated: bool,
+    #[serde(rename = "Contents")]
+    pub contents: Vec<S3Object>,
+    #[serde(rename = "CommonPrefixes")]
+    pub common_prefixes: Vec<S3CommonPrefix>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct S3Upload {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "UploadId")]
+    pub upload_id: String,
+    #[serde(rename = "Initiated")]
+    pub initiated: String,
+    #[serde(rename = "StorageClass")]
+    pub storage_class: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListMultipartUploadsResult")]
+pub struct S3ListMultipartUploadsResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: String,
+    #[serde(rename = "Bucket")]
+    pub bucket: String,
+    #[serde(rename = "KeyMarker")]
+    pub key_marker: String,
+    #[serde(rename = "UploadIdMarker")]
+    pub upload_id_marker: String,
+    #[serde(rename = "NextKeyMarker", skip_serializing_if = "Option::is_none")]
+    pub next_key_marker: Option<String>,
+    #[serde(rename = "NextUploadIdMarker", skip_serializing_if = "Option::is_none")]
+    pub next_upload_id_marker: Option<String>,
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
+    #[serde(rename = "EncodingType", skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<String>,
+    #[serde(rename = "MaxUploads")]
+    pub max_uploads: usize,
+    #[serde(rename = "IsTruncated")]
+    pub is_truncated: bool,
+    #[serde(rename = "Upload")]
+    pub uploads: Vec<S3Upload>,
+}
+
+pub async fn read_handler(
+    Path(bucket): Path<String>,
+    Query(params): Query<ReqParams>,
+    Extension(context): Extension<SignatureContext>,
+) -> AppResult<impl IntoResponse> {
+    let prefix = params.prefix.as_deref().unwrap_or_default();
+    context.authorize(CFGPermission::Read, &format!("{bucket}/{prefix}"))?;
+
+    if params.uploads.is_some() {
+        return list_multipart_uploads(bucket, params).await;
+    }
+
+    list_objects(bucket, params).await
+}
+
+/// HeadBucket
+/// 存在しないバケットはmiddleware::bucket::bucket_resolverでNoSuchBucketとなる
+pub async fn head_handler(Path(bucket): Path<String>, Extension(context): Extension<SignatureContext>) -> AppResult<impl IntoResponse> {
+    if !context.can_access_bucket(&bucket) {
+        return Err(S3Error::AccessDenied.into());
+    }
+
+    Ok(StatusCode::OK)
+}
+
+async fn list_objects(bucket: String, params: ReqParams) -> AppResult<axum::response::Response> {
+    let is_v2 = params.list_type == Some(2);
+    let start_after = if is_v2 {
+        match params.continuation_token.as_deref() {
+            Some(token) => match decode_continuation_token(token) {
+                Some(decoded) => Some(decoded),
+                None => return Err(S3Error::InvalidArgument("The continuation token provided is incorrect".to_string()).into()),
+            },
+            None => params.start_after.clone(),
+        }
+    } else {
+        params.marker.clone()
+    };
+
+    let prefix = params.prefix.unwrap_or_default();
+    let max_keys = params.max_keys.unwrap_or(MAX_KEYS_LIMIT).min(MAX_KEYS_LIMIT);
+    // オブジェクトのパスはエンコードされた形式で保存されているため、同じ形式で比較する
+    let options = storage::ListObjectsOptions {
+        prefix: encode_object_key(&prefix),
+        delimiter: params.delimiter.as_deref().map(encode_object_key),
+        start_after: start_after.as_deref().map(encode_object_key),
+        max_keys,
+    };
+
+    let result = storage::list_objects(&bucket, options).await?;
+
+    // encoding-type=url が指定された場合、キーなどはURLエンコードして返す
+    let is_url_encoding = params.encoding_type.as_deref() == Some("url");
+    let encode = |s: &str| {
+        if is_url_encoding {
+            urlencoding::encode(s).to_string()
+        } else {
+            s.to_string()
+        }
+    };
+
+    let base_path = format!("/{bucket}/");
+    let contents = result
+        .objects
+        .into_iter()
+        .map(|object| S3Object {
+            key: encode(&decode_object_key(&object.path[base_path.len()..])),
+            last_modified: object.updated_at.map(|t| t.to_utc().to_rfc3339_opts(SecondsFormat::Millis, true)),
+            e_tag: format_e_tag(&object),
+            size: object.content_size,
+            storage_class: "STANDARD".to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    let next_marker = result.next_marker.as_deref().map(decode_object_key);
+    let common_prefixes = result
+        .common_prefixes
+        .iter()
+        .map(|prefix| S3CommonPrefix {
+            prefix: encode(&decode_object_key(prefix)),
+        })
+        .collect::<Vec<_>>();
+
+    if is_v2 {
+        let response = S3ListBucketResultV2 {
+            xmlns: S3_XML_NAMESPACE.to_string(),
+            name: bucket,
+            prefix: encode(&prefix),
+            delimiter: params.delimiter.as_deref().map(encode),
+            start_after: params.start_after.as_deref().map(encode),
+            continuation_token: params.continuation_token,
+            next_continuation_token: next_marker.as_deref().map(encode_continuation_token),
+            encoding_type: params.encoding_type,
+            max_keys,
+            key_count: contents.len() + common_prefixes.len(),
+            is_truncated: result.is_truncated,
+            contents,
+            common_prefixes,
+        };
+
+        return xml_response(&response);
+    }
+
+    let response = S3ListBucketResult {
+        xmlns: S3_XML_NAMESPACE.to_string(),
+        name: bucket,
+        prefix: encode(&prefix),
+        delimiter: params.delimiter.as_deref().map(encode),
+        marker: start_after.as_deref().map(encode).unwrap_or_default(),
+        next_marker: next_marker.as_deref().map(encode),
+        encoding_type: params.encoding_type,
+        max_keys,
+        is_truncated: result.is_truncated,
+        contents,
+        common_prefixes,
+    };
+
+    xml_response(&response)
+}
+
+async fn list_multipart_uploads(bucket: String, params: ReqParams) -> AppResult<axum::response::Response> {
+    let prefix = params.prefix.unwrap_or_default();
+    let max_uploads = params.max_uploads.unwrap_or(MAX_KEYS_LIMIT).min(MAX_KEYS_LIMIT);
+    let options = storage::ListMultipartUploadsOptions {
+        prefix: encode_object_key(&prefix),
+        key_marker: params.key_marker.as_deref().map(encode_object_key),
+        upload_id_marker: params.upload_id_marker.clone(),
+        max_uploads,
+    };
+
+    let result = storage::list_multipart_uploads(&bucket, options).await?;
+
+    let is_url_encoding = params.encoding_type.as_deref() == Some("url");
+    let encode = |s: &str| {
+        if is_url_encoding {
+            urlencoding::encode(s).to_string()
+        } else {
+            s.to_string()
+        }
+    };
+
+    let base_path = format!("/{bucket}/");
+    let uploads = result
+        .uploads
+        .iter()
+        .map(|upload| S3Upload {
+            key: encode(&decode_object_key(&upload.path[base_path.len()..])),
+            upload_id: upload.upload_id.clone(),
+            initiated: upload.created_at.to_utc().to_rfc3339_opts(SecondsFormat::Millis, true),
+            storage_class: "STANDARD".to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    let last_upload = result.uploads.last().filter(|_| result.is_truncated);
+    let response = S3ListMultipartUploadsResult {
+        xmlns: S3_XML_NAMESPACE.to_string(),
+        bucket,
+        key_marker: params.key_marker.as_deref().map(encode).unwrap_or_default(),
+        upload_id_marker: params.upload_id_marker.unwrap_or_default(),
+        next_key_marker: last_upload.map(|upload| encode(&decode_object_key(&upload.path[base_path.len()..]))),
+        next_upload_id_marker: last_upload.map(|upload| upload.upload_id.clone()),
+        prefix: encode(&prefix),
+        encoding_type: params.encoding_type,
+        max_uploads,
+        is_truncated: result.is_truncated,
+        uploads,
+    };
+
+    xml_response(&response)
+}
+
+fn encode_continuation_token(marker: &str) -> String {
+    marker.bytes().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_continuation_token(token: &str) -> Option<String> {
+    if !token.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let bytes = (0..token.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(token.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continuation_token_round_trip() {
+        for marker in ["a", "dir1/", "日本語/キー", "a b+c"] {
+            assert_eq!(decode_continuation_token(&encode_continuation_token(marker)).as_deref(), Some(marker));
+        }
+
+        // 奇数の長さ、16進数ではない文字、UTF-8として不正なバイト列
+        for token in ["616", "zz", "ff"] {
+            assert_eq!(decode_continuation_token(token), None);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    config::CFGPermission,
+    server::{
+        AppResult,
+        error::S3Error,
+        middleware::signature::SignatureContext,
+        payload,
+        utils::{S3_XML_NAMESPACE, build_object_path, xml_response},
+    },
+    storage,
+};
+use axum::{
+    body::Body,
+    extract::{Path, Query, Request},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+
+/// 1回のリクエストで削除できるオブジェクトの最大数
+const MAX_DELETE_OBJECTS: usize = 1000;
+/// Deleteリクエストの本文の最大サイズ
+const MAX_DELETE_REQUEST_SIZE: usize = 2 * 1024 * 1024;
+/// キーの最大長 (バイト)
+const MAX_KEY_LENGTH: usize = 1024;
+
+#[derive(Deserialize, Debug)]
+pub struct ReqParams {
+    delete: Option<String>,
+}
+
+// S3 API Request Structures
+#[derive(Debug, Deserialize)]
+#[serde(rename = "Delete")]
+pub struct S3Delete {
+    #[serde(rename = "Object", default)]
+    pub objects: Vec<S3ObjectIdentifier>,
+    #[serde(rename = "Quiet", default)]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct S3ObjectIdentifier {
+    #[serde(rename = "Key")]
+    pub key: String,
+}
+
+// S3 API Response Structures
+#[derive(Debug, Serialize)]
+pub struct S3DeletedObject {
+    #[serde(rename = "Key")]
+    pub key: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct S3DeleteError {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "Code")]
+    pub code: String,
+    #[serde(rename = "Message")]
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "DeleteResult")]
+pub struct S3DeleteResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: String,
+    #[serde(rename = "Deleted")]
+    pub deleted: Vec<S3DeletedObject>,
+    #[serde(rename = "Error")]
+    pub errors: Vec<S3DeleteError>,
+}
+
+pub async fn write_handler(Path(bucket): Path<String>, Query(params): Query<ReqParams>, request: Request<Body>) -> AppResult<impl IntoResponse> {
+    if params.delete.is_none() {
+        return Err(S3Error::InvalidRequest("Unknown operation".to_string()).into());
+    }
+
+    delete_objects(bucket, request).await
+}
+
+async fn delete_objects(bucket: String, request: Request<Body>) -> AppResult<axum::response::Response> {
+    let (parts, body) = request.into_parts();
+    let context = parts.extensions.get::<SignatureContext>().ok_or(S3Error::AccessDenied)?;
+    let request_body = payload::read_xml_payload(body, Some(context), &parts.headers, MAX_DELETE_REQUEST_SIZE).await?;
+
+    let delete_request = serde_xml_rs::from_reader::<S3Delete, _>(request_body.as_slice());
+    if let Err(e) = delete_request {
+        tracing::debug!("Failed to parse Delete request: {}", e);
+        return Err(S3Error::MalformedXML.into());
+    }
+
+    let delete_request = delete_request.unwrap();
+    if delete_request.objects.is_empty() || delete_request.objects.len() > MAX_DELETE_OBJECTS {
+        return Err(S3Error::MalformedXML.into());
+    }
+
+    let mut keys = Vec::new();
+    let mut errors = Vec::new();
+    for object in delete_request.objects {
+        if object.key.is_empty() {
+            errors.push(S3DeleteError {
+                key: object.key,
+                code: "InvalidArgument".to_string(),
+                message: "Key must not be empty".to_string(),
+            });
+        } else if object.key.len() > MAX_KEY_LENGTH {
+            errors.push(S3DeleteError {
+                key: object.key,
+                code: "KeyTooLongError".to_string(),
+                message: "Your key is too long".to_string(),
+            });
+        } else if let Err(e) = context.authorize(CFGPermission::Delete, &format!("{bucket}/{}", object.key)) {
+            errors.push(S3DeleteError {
+                key: object.key,
+                code: e.code().to_string(),
+                message: e.message(),
+            });
+        } else {
+            keys.push(object.key);
+        }
+    }
+
+    let paths = keys.iter().map(|key| build_object_path(&bucket, key)).collect::<Vec<String>>();
+    storage::delete_objects(&paths).await?;
+
+    // Quietモードの場合はエラーのみを返す
+    let deleted = if delete_request.quiet {
+        vec![]
+    } else {
+        keys.into_iter().map(|key| S3DeletedObject { key }).collect()
+    };
+
+    let response = S3DeleteResult {
+        xmlns: S3_XML_NAMESPACE.to_string(),
+        deleted,
+        errors,
+    };
+
+    xml_response(&response)
+}
@@ -1,36 +1,172 @@
 use crate::{
-    server::{AppResult, utils::build_content_disposition_filename},
+    config::{self, CFGPermission},
+    server::{
+        AppResult,
+        error::S3Error,
+        middleware::signature::SignatureContext,
+        utils::{S3_XML_NAMESPACE, build_content_disposition_filename, decode_object_path, format_e_tag, insert_object_headers, xml_response},
+    },
     storage,
 };
 use axum::{
     body::Body,
-    extract::Request,
-    http::{HeaderMap, Method, StatusCode},
-    response::IntoResponse,
+    extract::{Query, Request},
+    http::{HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::{
+    TypedHeader,
+    headers::{ETag, HeaderMapExt, IfMatch, IfModifiedSince, IfNoneMatch, IfUnmodifiedSince, LastModified, Range},
 };
-use axum_extra::{TypedHeader, headers::Range};
 use axum_range::{KnownSize, Ranged};
+use chrono::SecondsFormat;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+const MAX_PARTS_LIMIT: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct ReqParams {
+    #[serde(rename = "uploadId")]
+    upload_id: Option<String>,
+    #[serde(rename = "max-parts")]
+    max_parts: Option<usize>,
+    #[serde(rename = "part-number-marker")]
+    part_number_marker: Option<i32>,
+    #[serde(rename = "response-content-type")]
+    response_content_type: Option<String>,
+    #[serde(rename = "response-content-language")]
+    response_content_language: Option<String>,
+    #[serde(rename = "response-expires")]
+    response_expires: Option<String>,
+    #[serde(rename = "response-cache-control")]
+    response_cache_control: Option<String>,
+    #[serde(rename = "response-content-disposition")]
+    response_content_disposition: Option<String>,
+    #[serde(rename = "response-content-encoding")]
+    response_content_encoding: Option<String>,
+}
+
+impl ReqParams {
+    /// `response-*`パラメータで上書きするレスポンスヘッダー
+    fn response_header_overrides(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("Content-Type", &self.response_content_type),
+            ("Content-Language", &self.response_content_language),
+            ("Expires", &self.response_expires),
+            ("Cache-Control", &self.response_cache_control),
+            ("Content-Disposition", &self.response_content_disposition),
+            ("Content-Encoding", &self.response_content_encoding),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+        .collect()
+    }
+}
+
+// S3 API Response Structures
+#[derive(Debug, Serialize)]
+pub struct S3Part {
+    #[serde(rename = "PartNumber")]
+    pub part_number: i32,
+    #[serde(rename = "LastModified")]
+    pub last_modified: String,
+    #[serde(rename = "ETag")]
+    pub e_tag: String,
+    #[serde(rename = "Size")]
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListPartsResult")]
+pub struct S3ListPartsResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: String,
+    #[serde(rename = "Bucket")]
+    pub bucket: String,
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "UploadId")]
+    pub upload_id: String,
+    #[serde(rename = "PartNumberMarker")]
+    pub part_number_marker: i32,
+    #[serde(rename = "NextPartNumberMarker", skip_serializing_if = "Option::is_none")]
+    pub next_part_number_marker: Option<i32>,
+    #[serde(rename = "MaxParts")]
+    pub max_parts: usize,
+    #[serde(rename = "IsTruncated")]
+    pub is_truncated: bool,
+    #[serde(rename = "StorageClass")]
+    pub storage_class: String,
+    #[serde(rename = "Part")]
+    pub parts: Vec<S3Part>,
+}
 
-pub async fn read_handler(method: Method, range: Option<TypedHeader<Range>>, request: Request<Body>) -> AppResult<impl IntoResponse> {
+pub async fn read_handler(
+    method: Method,
+    range: Option<TypedHeader<Range>>,
+    Query(params): Query<ReqParams>,
+    request: Request<Body>,
+) -> AppResult<impl IntoResponse> {
     let object_path = request.uri().path().to_string();
     if object_path.is_empty() {
-        return Ok(StatusCode::BAD_REQUEST.into_response());
+        return Err(S3Error::InvalidRequest("Object path is empty".to_string()).into());
     }
 
-    let is_head_request = method == Method::HEAD;
-    let object_data = storage::get_object(object_path, !is_head_request).await;
-    if object_data.is_err() {
-        return Ok((StatusCode::NOT_FOUND, "Object not found").into_response());
+    if method == Method::GET && params.upload_id.is_some() {
+        // 読み取りは匿名でも許可しているが、アップロード中のパートの一覧は署名付きリクエストでのみ返す
+        let context = request.extensions().get::<SignatureContext>().ok_or(S3Error::AccessDenied)?;
+        context.authorize(CFGPermission::Read, &decode_object_path(&object_path))?;
+
+        return list_parts(object_path, params).await;
+    }
+
+    // 署名付きリクエストの場合は、署名したアクセスキーに読み取りが許可されている必要がある
+    // 非公開の接頭辞に含まれるオブジェクトは匿名では読み取れない
+    let resource = decode_object_path(&object_path);
+    match request.extensions().get::<SignatureContext>() {
+        Some(context) => context.authorize(CFGPermission::Read, &resource)?,
+        None if is_private_path(&resource) => return Err(S3Error::AccessDenied.into()),
+        None => {}
+    }
+
+    // 署名に含まれるためURLを書き換えられない、署名付きリクエストでのみ上書きを許可する
+    let header_overrides = params.response_header_overrides();
+    if !header_overrides.is_empty() && request.extensions().get::<SignatureContext>().is_none() {
+        return Err(S3Error::InvalidRequest("Request specific response headers cannot be used for anonymous GET requests.".to_string()).into());
     }
-    let object_data = object_data.unwrap();
+
+    let header_overrides = header_overrides
+        .into_iter()
+        .map(|(name, value)| match value.parse::<HeaderValue>() {
+            Ok(value) => Ok((name, value)),
+            Err(_) => Err(S3Error::InvalidArgument(format!("Invalid value of the response header override: {name}"))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let is_head_request = method == Method::HEAD;
+    let object_data = storage::get_object(object_path, !is_head_request).await?;
 
     // set headers
     let mut headers = HeaderMap::new();
+    // アップロード時にCache-Controlが指定されていない場合のみ、長期間のキャッシュを許可する
     headers.insert("Cache-Control", "max-age=31536000, immutable".parse().unwrap());
     headers.insert("Content-Type", object_data.metadata.mime_type.parse().unwrap());
-    headers.insert("ETag", format!("\"{}\"", object_data.metadata.internal_filename).parse().unwrap());
+    insert_object_headers(&mut headers, &storage::ObjectHeaders::from_json(object_data.metadata.headers.as_ref()));
+    let e_tag = format_e_tag(&object_data.metadata).parse::<ETag>().unwrap();
+    headers.typed_insert(e_tag.clone());
     headers.insert("Accept-Ranges", "bytes".parse().unwrap());
 
+    // 更新日時が記録されていないオブジェクトはLast-Modifiedを返さず、日時の条件も評価しない
+    let last_modified = object_data.metadata.updated_at.map(SystemTime::from);
+    if let Some(last_modified) = last_modified {
+        headers.typed_insert(LastModified::from(last_modified));
+    }
+
+    if !evaluate_preconditions(request.headers(), &e_tag, last_modified)? {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
     let mut content_disposition = vec!["inline".to_string()];
     content_disposition.extend(build_content_disposition_filename(
         object_data.metadata.filename,
@@ -38,6 +174,10 @@ pub async fn read_handler(method: Method, range: Option<TypedHeader<Range>>, req
     ));
     headers.insert("Content-Disposition", content_disposition.join("; ").parse().unwrap());
 
+    for (name, value) in header_overrides {
+        headers.insert(name, value);
+    }
+
     if is_head_request {
         headers.insert("Content-Length", object_data.metadata.content_size.to_string().parse().unwrap());
         return Ok((StatusCode::OK, headers).into_response());
@@ -49,3 +189,76 @@ pub async fn read_handler(method: Method, range: Option<TypedHeader<Range>>, req
 
     Ok(response)
 }
+
+fn is_private_path(path: &str) -> bool {
+    config::CONFIG
+        .bucket
+        .private_prefixes
+        .iter()
+        .any(|prefix| path.starts_with(prefix.as_str()))
+}
+
+async fn list_parts(object_path: String, params: ReqParams) -> AppResult<Response> {
+    let upload_id = params.upload_id.unwrap_or_default();
+    let part_number_marker = params.part_number_marker.unwrap_or(0);
+    let max_parts = params.max_parts.unwrap_or(MAX_PARTS_LIMIT).min(MAX_PARTS_LIMIT);
+
+    let result = storage::list_parts(&upload_id, &object_path, part_number_marker, max_parts).await?;
+
+    let (bucket, key) = object_path.trim_start_matches('/').split_once('/').unwrap_or_default();
+    let parts = result
+        .parts
+        .iter()
+        .map(|part| S3Part {
+            part_number: part.part_number,
+            last_modified: part.updated_at.to_utc().to_rfc3339_opts(SecondsFormat::Millis, true),
+            e_tag: format!("\"{}\"", part.e_tag.clone().unwrap_or_default()),
+            size: part.content_size,
+        })
+        .collect::<Vec<_>>();
+
+    let response = S3ListPartsResult {
+        xmlns: S3_XML_NAMESPACE.to_string(),
+        bucket: bucket.to_string(),
+        key: urlencoding::decode(key).map(|key| key.to_string()).unwrap_or(key.to_string()),
+        upload_id,
+        part_number_marker,
+        next_part_number_marker: result.parts.last().filter(|_| result.is_truncated).map(|part| part.part_number),
+        max_parts,
+        is_truncated: result.is_truncated,
+        storage_class: "STANDARD".to_string(),
+        parts,
+    };
+
+    xml_response(&response)
+}
+
+/// 条件付きリクエストのヘッダーを評価する
+/// 412を返すべき場合はエラーを、304を返すべき場合はfalseを返す
+/// See: https://datatracker.ietf.org/doc/html/rfc9110#section-13.2.2
+fn evaluate_preconditions(headers: &HeaderMap, e_tag: &ETag, last_modified: Option<SystemTime>) -> Result<bool, S3Error> {
+    // If-Matchが指定された場合はIf-Unmodified-Sinceを無視する
+    if let Some(if_match) = headers.typed_get::<IfMatch>() {
+        if !if_match.precondition_passes(e_tag) {
+            return Err(S3Error::PreconditionFailed);
+        }
+    } else if let Some(if_unmodified_since) = headers.typed_get::<IfUnmodifiedSince>() &&
+        let Some(last_modified) = last_modified &&
+        !if_unmodified_since.precondition_passes(last_modified)
+    {
+        return Err(S3Error::PreconditionFailed);
+    }
+
+    // If-None-Matchが指定された場合はIf-Modified-Sinceを無視する
+    if let Some(if_none_match) = headers.typed_get::<IfNoneMatch>() {
+        return Ok(if_none_match.precondition_passes(e_tag));
+    }
+
+    if let Some(if_modified_since) = headers.typed_get::<IfModifiedSince>() &&
+        let Some(last_modified) = last_modified
+    {
+        return Ok(if_modified_since.is_modified(last_modified));
+    }
+
+    Ok(true)
+}
@@ -1,29 +1,41 @@
 use crate::{
+    config::{BucketConfig, CFGPermission},
     server::{
         AppResult,
-        middleware::multipart::MultipartUploadState,
-        utils::{get_header, parse_content_disposition},
+        error::S3Error,
+        middleware::{multipart::MultipartUploadState, signature::SignatureContext},
+        operation::OperationType,
+        payload,
+        utils::{build_object_path, decode_object_path, format_e_tag, get_header, parse_content_disposition, parse_object_headers, xml_response},
     },
     storage,
 };
 use axum::{
     body::Body,
     extract::Request,
-    http::{Method, Response, StatusCode},
+    http::{Response, StatusCode},
     response::IntoResponse,
 };
-use serde::Serialize;
-use uuid::Uuid;
-
-#[derive(PartialEq, Debug)]
-enum OperationType {
-    PutObject,
-    CreateMultipartUpload,
-    UploadPart,
-    CompleteMultipartUpload,
-    AbortMultipartUpload,
-    DeleteObject,
-    Unknown,
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+/// CompleteMultipartUploadリクエストの本文の最大サイズ
+const MAX_COMPLETE_REQUEST_SIZE: usize = 2 * 1024 * 1024;
+
+// S3 API Request Structures
+#[derive(Debug, Deserialize)]
+#[serde(rename = "CompleteMultipartUpload")]
+pub struct S3CompleteMultipartUpload {
+    #[serde(rename = "Part", default)]
+    pub parts: Vec<S3CompletedPart>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct S3CompletedPart {
+    #[serde(rename = "PartNumber")]
+    pub part_number: i32,
+    #[serde(rename = "ETag")]
+    pub e_tag: String,
 }
 
 // S3 API Response Structures
@@ -38,6 +50,24 @@ pub struct S3InitiateMultipartUploadResult {
     pub upload_id: String,
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename = "CopyObjectResult")]
+pub struct S3CopyObjectResult {
+    #[serde(rename = "ETag")]
+    pub e_tag: String,
+    #[serde(rename = "LastModified")]
+    pub last_modified: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "CopyPartResult")]
+pub struct S3CopyPartResult {
+    #[serde(rename = "ETag")]
+    pub e_tag: String,
+    #[serde(rename = "LastModified")]
+    pub last_modified: String,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename = "CompleteMultipartUploadResult")]
 pub struct S3CompleteMultipartUploadResult {
@@ -54,65 +84,90 @@ pub struct S3CompleteMultipartUploadResult {
 pub async fn write_handler(request: Request<Body>) -> AppResult<impl IntoResponse> {
     let object_path = request.uri().path().to_string();
     if object_path.is_empty() {
-        return Ok(StatusCode::BAD_REQUEST.into_response());
+        return Err(S3Error::InvalidRequest("Object path is empty".to_string()).into());
     }
 
+    let operation = OperationType::from_request(&request);
     let (parts, body) = request.into_parts();
     let multipart_upload_state = parts.extensions.get::<MultipartUploadState>().unwrap();
-    let is_multipart_operation = multipart_upload_state.upload_id.as_ref().is_some();
-
-    let operation = match parts.method {
-        Method::PUT => {
-            if is_multipart_operation {
-                OperationType::UploadPart
-            } else {
-                OperationType::PutObject
-            }
-        }
-        Method::POST => {
-            if is_multipart_operation {
-                OperationType::CompleteMultipartUpload
-            } else {
-                OperationType::CreateMultipartUpload
-            }
-        }
-        Method::DELETE => {
-            if is_multipart_operation {
-                OperationType::AbortMultipartUpload
-            } else {
-                OperationType::DeleteObject
-            }
-        }
-        _ => OperationType::Unknown,
-    };
+    let bucket = parts.extensions.get::<BucketConfig>().unwrap();
+    let copy_source = get_header(&parts.headers, "X-Amz-Copy-Source", None);
 
     if operation == OperationType::Unknown {
-        return Ok((StatusCode::BAD_REQUEST, "unknown operation").into_response());
+        return Err(S3Error::InvalidRequest("Unknown operation".to_string()).into());
     }
 
     tracing::debug!("Operation: {:?}", operation);
 
+    let context = parts.extensions.get::<SignatureContext>().ok_or(S3Error::AccessDenied)?;
+    let permission = if operation == OperationType::DeleteObject {
+        CFGPermission::Delete
+    } else {
+        CFGPermission::Write
+    };
+    context.authorize(permission, &decode_object_path(&object_path))?;
+
     let mime_type = get_header(&parts.headers, "Content-Type", Some("application/octet-stream".to_string()));
-    let content_size = get_header(&parts.headers, "Content-Length", None).parse::<i64>().unwrap_or(0);
+    // aws-chunkedの場合、Content-Lengthはエンコード後のサイズになる
+    let decoded_content_length = get_header(&parts.headers, "X-Amz-Decoded-Content-Length", None).parse::<i64>().ok();
+    let content_size = decoded_content_length.unwrap_or(get_header(&parts.headers, "Content-Length", None).parse::<i64>().unwrap_or(0));
     let content_disposition = parse_content_disposition(get_header(&parts.headers, "Content-Disposition", None).as_str());
+    let object_headers = parse_object_headers(&parts.headers)?;
+
+    // ヘッダーで宣言されたサイズが上限を超える場合はボディを読まずに拒否し、宣言と異なるサイズのボディは読み込みながら制限する
+    let max_upload_size = bucket.max_upload_size_mb * 1024 * 1024;
+    if content_size as u64 > max_upload_size {
+        return Err(S3Error::EntityTooLarge.into());
+    }
 
     match operation {
         OperationType::PutObject => {
+            let binary = payload::verify_payload(body, Some(context), &parts.headers, max_upload_size)?;
             let write_object_data = storage::WriteObjectData {
-                binary: body.into_data_stream(),
+                binary,
                 path: object_path,
                 mime_type,
-                content_size,
                 filename: content_disposition.filename.clone(),
                 encoded_filename: content_disposition.encoded_filename.clone(),
+                headers: object_headers,
             };
 
-            let result = storage::put_object(write_object_data).await;
-            if let Err(e) = result {
-                return Err(e.into());
-            }
+            let metadata = storage::put_object(write_object_data).await?;
+            let response = Response::builder()
+                .status(StatusCode::CREATED)
+                .header("ETag", format_e_tag(&metadata))
+                .body(Body::empty())
+                .unwrap();
+
+            Ok(response)
+        }
+        OperationType::CopyObject => {
+            let source_path = parse_copy_source(&copy_source)?;
+            context.authorize(CFGPermission::Read, &decode_object_path(&source_path))?;
+            let metadata_directive = get_header(&parts.headers, "X-Amz-Metadata-Directive", Some("COPY".to_string()));
+            let replace = match metadata_directive.to_uppercase().as_str() {
+                "COPY" => None,
+                "REPLACE" => Some(storage::ReplaceMetadata {
+                    filename: content_disposition.filename,
+                    encoded_filename: content_disposition.encoded_filename,
+                    mime_type,
+                    headers: object_headers,
+                }),
+                _ => {
+                    return Err(S3Error::InvalidArgument(format!("Unknown metadata directive: {metadata_directive}")).into());
+                }
+            };
 
-            Ok(StatusCode::CREATED.into_response())
+            let metadata = storage::copy_object(source_path, object_path, replace).await?;
+            let response = S3CopyObjectResult {
+                e_tag: format_e_tag(&metadata),
+                last_modified: metadata
+                    .updated_at
+                    .map_or(Utc::now(), |t| t.to_utc())
+                    .to_rfc3339_opts(SecondsFormat::Millis, true),
+            };
+
+            xml_response(&response)
         }
         OperationType::CreateMultipartUpload => {
             let upload_id = storage::create_multipart_upload(
@@ -120,7 +175,9 @@ pub async fn write_handler(request: Request<Body>) -> AppResult<impl IntoRespons
                 content_disposition.filename,
                 content_disposition.encoded_filename,
                 mime_type,
-            );
+                object_headers,
+            )
+            .await?;
 
             let (bucket, key) = object_path.split_once('/').unwrap_or(("", &object_path));
             let response = S3InitiateMultipartUploadResult {
@@ -129,48 +186,87 @@ pub async fn write_handler(request: Request<Body>) -> AppResult<impl IntoRespons
                 upload_id,
             };
 
-            let xml_response = serde_xml_rs::to_string(&response);
-            if let Err(e) = xml_response {
-                return Err(e.into());
-            }
-
-            let mut response = Response::new(xml_response.unwrap());
-            response.headers_mut().insert("Content-Type", "application/xml".parse().unwrap());
-
-            Ok(response.into_response())
+            xml_response(&response)
         }
         OperationType::UploadPart => {
             if multipart_upload_state.upload_id.is_none() || multipart_upload_state.part_number.is_none() {
-                return Ok((StatusCode::BAD_REQUEST, "Missing uploadId or partNumber").into_response());
+                return Err(S3Error::InvalidRequest("Missing uploadId or partNumber".to_string()).into());
             }
 
             if !multipart_upload_state.is_registered {
-                return Ok((StatusCode::BAD_REQUEST, "Invalid or expires uploadId").into_response());
+                return Err(S3Error::NoSuchUpload.into());
             }
 
             let upload_id = multipart_upload_state.upload_id.as_ref().unwrap();
             let part_number = multipart_upload_state.part_number.unwrap();
-            storage::upload_part(upload_id.clone(), part_number, body.into_data_stream()).await?;
+            let binary = payload::verify_payload(body, Some(context), &parts.headers, max_upload_size)?;
+            let e_tag = storage::upload_part(upload_id.clone(), part_number, binary).await?;
 
             let response = Response::builder()
                 .status(StatusCode::OK)
-                .header("ETag", Uuid::new_v4().to_string())
+                .header("ETag", format!("\"{e_tag}\""))
                 .body(Body::empty())
                 .unwrap();
 
             Ok(response)
         }
+        OperationType::UploadPartCopy => {
+            if multipart_upload_state.upload_id.is_none() || multipart_upload_state.part_number.is_none() {
+                return Err(S3Error::InvalidRequest("Missing uploadId or partNumber".to_string()).into());
+            }
+
+            if !multipart_upload_state.is_registered {
+                return Err(S3Error::NoSuchUpload.into());
+            }
+
+            let source_path = parse_copy_source(&copy_source)?;
+            context.authorize(CFGPermission::Read, &decode_object_path(&source_path))?;
+            let copy_source_range = get_header(&parts.headers, "X-Amz-Copy-Source-Range", None);
+            let range = if copy_source_range.is_empty() {
+                None
+            } else {
+                Some(parse_copy_source_range(&copy_source_range)?)
+            };
+
+            let upload_id = multipart_upload_state.upload_id.as_ref().unwrap();
+            let part_number = multipart_upload_state.part_number.unwrap();
+            let e_tag = storage::upload_part_copy(upload_id.clone(), part_number, source_path, range).await?;
+
+            let response = S3CopyPartResult {
+                e_tag: format!("\"{e_tag}\""),
+                last_modified: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            };
+
+            xml_response(&response)
+        }
         OperationType::CompleteMultipartUpload => {
             let upload_id = multipart_upload_state.upload_id.clone();
             if upload_id.is_none() {
-                return Ok((StatusCode::BAD_REQUEST, "Missing uploadId").into_response());
+                return Err(S3Error::InvalidRequest("Missing uploadId".to_string()).into());
             }
 
             if !multipart_upload_state.is_registered {
-                return Ok((StatusCode::BAD_REQUEST, "Invalid or expired uploadId").into_response());
+                return Err(S3Error::NoSuchUpload.into());
+            }
+
+            let request_body = payload::read_xml_payload(body, Some(context), &parts.headers, MAX_COMPLETE_REQUEST_SIZE).await?;
+            let complete_request = serde_xml_rs::from_reader::<S3CompleteMultipartUpload, _>(request_body.as_slice());
+            if let Err(e) = complete_request {
+                tracing::debug!("Failed to parse CompleteMultipartUpload request: {}", e);
+                return Err(S3Error::MalformedXML.into());
             }
 
-            storage::complete_multipart_upload(upload_id.unwrap()).await?;
+            let completed_parts = complete_request
+                .unwrap()
+                .parts
+                .into_iter()
+                .map(|part| storage::CompletedPart {
+                    part_number: part.part_number,
+                    e_tag: part.e_tag,
+                })
+                .collect();
+
+            let metadata = storage::complete_multipart_upload(upload_id.unwrap(), completed_parts).await?;
 
             let location = parts.uri.to_string();
             let (bucket, key) = object_path.split_once('/').unwrap_or(("", &object_path));
@@ -178,36 +274,66 @@ pub async fn write_handler(request: Request<Body>) -> AppResult<impl IntoRespons
                 location: location.split_once('?').map_or(location.clone(), |(loc, _)| loc.to_string()),
                 bucket: bucket.to_string(),
                 key: key.to_string(),
-                e_tag: Uuid::new_v4().to_string(),
+                e_tag: format_e_tag(&metadata),
             };
 
-            let xml_response = serde_xml_rs::to_string(&response);
-            if let Err(e) = xml_response {
-                return Err(e.into());
-            }
-
-            let mut response = Response::new(xml_response.unwrap());
-            response.headers_mut().insert("Content-Type", "application/xml".parse().unwrap());
-
-            Ok(response.into_response())
+            xml_response(&response)
         }
         OperationType::AbortMultipartUpload => {
             let upload_id = multipart_upload_state.upload_id.clone();
             if upload_id.is_none() {
-                return Ok((StatusCode::BAD_REQUEST, "Missing uploadId").into_response());
+                return Err(S3Error::InvalidRequest("Missing uploadId".to_string()).into());
+            }
+
+            if !multipart_upload_state.is_registered {
+                return Err(S3Error::NoSuchUpload.into());
             }
 
             storage::abort_multipart_upload(upload_id.unwrap()).await?;
             Ok(StatusCode::NO_CONTENT.into_response())
         }
         OperationType::DeleteObject => {
+            // S3では存在しないキーの削除も成功として扱う
             let result = storage::delete_object(object_path).await;
-            if let Err(e) = result {
+            if let Err(e) = result &&
+                e.downcast_ref::<S3Error>() != Some(&S3Error::NoSuchKey)
+            {
                 return Err(e.into());
             }
 
             Ok(StatusCode::NO_CONTENT.into_response())
         }
-        _ => Ok((StatusCode::BAD_REQUEST, "Unknown operation type").into_response()),
+        _ => Err(S3Error::InvalidRequest("Unknown operation type".to_string()).into()),
+    }
+}
+
+/// `x-amz-copy-source` (`/bucket/key` または `bucket/key`) をオブジェクトのパスに変換する
+fn parse_copy_source(copy_source: &str) -> Result<String, S3Error> {
+    // バージョニングには対応していないため、versionIdは無視する
+    let copy_source = copy_source.split_once('?').map_or(copy_source, |(source, _)| source);
+    let decoded = urlencoding::decode(copy_source);
+    if decoded.is_err() {
+        return Err(S3Error::InvalidArgument("Invalid copy source encoding".to_string()));
+    }
+
+    let decoded = decoded.unwrap();
+    let decoded = decoded.trim_start_matches('/');
+    let (bucket, key) = decoded.split_once('/').unwrap_or((decoded, ""));
+    if bucket.is_empty() || key.is_empty() {
+        return Err(S3Error::InvalidArgument(
+            "Copy Source must mention the source bucket and key: sourcebucket/sourcekey".to_string(),
+        ));
     }
+
+    Ok(build_object_path(bucket, key))
+}
+
+/// `x-amz-copy-source-range` (`bytes=first-last`) をパースする
+fn parse_copy_source_range(range: &str) -> Result<(u64, u64), S3Error> {
+    let invalid_range = || S3Error::InvalidArgument("The x-amz-copy-source-range value must be of the form bytes=first-last".to_string());
+    let (first, last) = range.strip_prefix("bytes=").and_then(|r| r.split_once('-')).ok_or_else(invalid_range)?;
+    let first = first.trim().parse::<u64>().map_err(|_| invalid_range())?;
+    let last = last.trim().parse::<u64>().map_err(|_| invalid_range())?;
+
+    Ok((first, last))
 }
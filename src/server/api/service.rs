@@ -0,0 +1,83 @@
+use crate::{
+    config,
+    server::{
+        AppResult,
+        api::r#static,
+        middleware::signature::SignatureContext,
+        utils::{S3_XML_NAMESPACE, xml_response},
+    },
+    storage,
+};
+use axum::{
+    body::Body,
+    extract::Request,
+    response::{IntoResponse, Response},
+};
+use chrono::SecondsFormat;
+use serde::Serialize;
+
+// S3 API Response Structures
+#[derive(Debug, Serialize)]
+pub struct S3Owner {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "DisplayName")]
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct S3Bucket {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "CreationDate", skip_serializing_if = "Option::is_none")]
+    pub creation_date: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct S3Buckets {
+    #[serde(rename = "Bucket")]
+    pub buckets: Vec<S3Bucket>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListAllMyBucketsResult")]
+pub struct S3ListAllMyBucketsResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: String,
+    #[serde(rename = "Owner")]
+    pub owner: S3Owner,
+    #[serde(rename = "Buckets")]
+    pub buckets: S3Buckets,
+}
+
+pub async fn read_handler(request: Request<Body>) -> AppResult<Response> {
+    let Some(context) = request.extensions().get::<SignatureContext>() else {
+        return Ok(r#static::index().await.into_response());
+    };
+
+    list_buckets(context)
+}
+
+/// 宣言されているバケットのうち、アクセスキーが操作できるバケットを返す
+fn list_buckets(context: &SignatureContext) -> AppResult<Response> {
+    let buckets = config::CONFIG
+        .declared_buckets()
+        .into_iter()
+        .filter(|bucket| context.can_access_bucket(&bucket.name))
+        .map(|bucket| S3Bucket {
+            creation_date: storage::bucket_created_at(&bucket).map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            name: bucket.name,
+        })
+        .collect::<Vec<_>>();
+
+    let response = S3ListAllMyBucketsResult {
+        xmlns: S3_XML_NAMESPACE.to_string(),
+        owner: S3Owner {
+            id: "ofuton".to_string(),
+            display_name: "ofuton".to_string(),
+        },
+        buckets: S3Buckets { buckets },
+    };
+
+    xml_response(&response)
+}
@@ -0,0 +1,162 @@
+use axum::{
+    body::Body,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum S3Error {
+    NoSuchKey,
+    NoSuchBucket,
+    NoSuchUpload,
+    InvalidPart,
+    InvalidPartOrder,
+    InvalidArgument(String),
+    InvalidRequest(String),
+    MalformedXML,
+    InvalidAccessKeyId,
+    AuthorizationQueryParametersError(String),
+    SignatureDoesNotMatch,
+    RequestTimeTooSkewed,
+    EntityTooLarge,
+    EntityTooSmall,
+    MetadataTooLarge,
+    IncompleteBody,
+    PreconditionFailed,
+    XAmzContentSHA256Mismatch,
+    BadDigest,
+    AccessDenied,
+    InternalError,
+    NotImplemented(String),
+}
+
+impl S3Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            S3Error::NoSuchKey => "NoSuchKey",
+            S3Error::NoSuchBucket => "NoSuchBucket",
+            S3Error::NoSuchUpload => "NoSuchUpload",
+            S3Error::InvalidPart => "InvalidPart",
+            S3Error::InvalidPartOrder => "InvalidPartOrder",
+            S3Error::InvalidArgument(_) => "InvalidArgument",
+            S3Error::InvalidRequest(_) => "InvalidRequest",
+            S3Error::MalformedXML => "MalformedXML",
+            S3Error::InvalidAccessKeyId => "InvalidAccessKeyId",
+            S3Error::AuthorizationQueryParametersError(_) => "AuthorizationQueryParametersError",
+            S3Error::SignatureDoesNotMatch => "SignatureDoesNotMatch",
+            S3Error::RequestTimeTooSkewed => "RequestTimeTooSkewed",
+            S3Error::EntityTooLarge => "EntityTooLarge",
+            S3Error::EntityTooSmall => "EntityTooSmall",
+            S3Error::MetadataTooLarge => "MetadataTooLarge",
+            S3Error::IncompleteBody => "IncompleteBody",
+            S3Error::PreconditionFailed => "PreconditionFailed",
+            S3Error::XAmzContentSHA256Mismatch => "XAmzContentSHA256Mismatch",
+            S3Error::BadDigest => "BadDigest",
+            S3Error::AccessDenied => "AccessDenied",
+            S3Error::InternalError => "InternalError",
+            S3Error::NotImplemented(_) => "NotImplemented",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            S3Error::NoSuchKey => "The specified key does not exist.".to_string(),
+            S3Error::NoSuchBucket => "The specified bucket does not exist.".to_string(),
+            S3Error::NoSuchUpload => "The specified multipart upload does not exist.".to_string(),
+            S3Error::InvalidPart => "One or more of the specified parts could not be found. The part might not have been uploaded, or the specified entity tag might not have matched the part's entity tag.".to_string(),
+            S3Error::InvalidPartOrder => "The list of parts was not in ascending order. Parts must be ordered by part number.".to_string(),
+            S3Error::InvalidArgument(message) => message.clone(),
+            S3Error::InvalidRequest(message) => message.clone(),
+            S3Error::MalformedXML => "The XML you provided was not well-formed or did not validate against our published schema.".to_string(),
+            S3Error::InvalidAccessKeyId => "The access key ID you provided does not exist in our records.".to_string(),
+            S3Error::AuthorizationQueryParametersError(message) => message.clone(),
+            S3Error::SignatureDoesNotMatch => "The request signature we calculated does not match the signature you provided.".to_string(),
+            S3Error::RequestTimeTooSkewed => "The difference between the request time and the server's time is too large.".to_string(),
+            S3Error::EntityTooLarge => "Your proposed upload exceeds the maximum allowed object size.".to_string(),
+            S3Error::EntityTooSmall => "Your proposed upload is smaller than the minimum allowed object size.".to_string(),
+            S3Error::MetadataTooLarge => "Your metadata headers exceed the maximum allowed metadata size.".to_string(),
+            S3Error::IncompleteBody => "You did not provide the number of bytes specified by the Content-Length HTTP header.".to_string(),
+            S3Error::PreconditionFailed => "At least one of the pre-conditions you specified did not hold.".to_string(),
+            S3Error::XAmzContentSHA256Mismatch => "The provided 'x-amz-content-sha256' header does not match what was computed.".to_string(),
+            S3Error::BadDigest => "The checksum you specified did not match what we received.".to_string(),
+            S3Error::AccessDenied => "Access Denied".to_string(),
+            S3Error::InternalError => "We encountered an internal error. Please try again.".to_string(),
+            S3Error::NotImplemented(message) => message.clone(),
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            S3Error::NoSuchKey | S3Error::NoSuchBucket | S3Error::NoSuchUpload => StatusCode::NOT_FOUND,
+            S3Error::InvalidPart |
+            S3Error::InvalidPartOrder |
+            S3Error::InvalidArgument(_) |
+            S3Error::InvalidRequest(_) |
+            S3Error::MalformedXML |
+            S3Error::AuthorizationQueryParametersError(_) |
+            S3Error::EntityTooLarge |
+            S3Error::EntityTooSmall |
+            S3Error::MetadataTooLarge |
+            S3Error::IncompleteBody |
+            S3Error::XAmzContentSHA256Mismatch |
+            S3Error::BadDigest => StatusCode::BAD_REQUEST,
+            S3Error::InvalidAccessKeyId | S3Error::SignatureDoesNotMatch | S3Error::RequestTimeTooSkewed | S3Error::AccessDenied => {
+                StatusCode::FORBIDDEN
+            }
+            S3Error::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            S3Error::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            S3Error::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+        }
+    }
+
+    /// `<Error>` ドキュメントを含むレスポンスを生成する
+    pub fn render(&self, resource: &str, request_id: &str) -> Response {
+        let body = S3ErrorResponse {
+            code: self.code().to_string(),
+            message: self.message(),
+            resource: resource.to_string(),
+            request_id: request_id.to_string(),
+        };
+
+        let xml_body = serde_xml_rs::to_string(&body).unwrap_or_default();
+        let mut response = (self.status_code(), xml_body).into_response();
+        response.headers_mut().insert("Content-Type", "application/xml".parse().unwrap());
+        if let Ok(request_id) = request_id.parse() {
+            response.headers_mut().insert("x-amz-request-id", request_id);
+        }
+
+        response
+    }
+}
+
+impl fmt::Display for S3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for S3Error {}
+
+impl IntoResponse for S3Error {
+    fn into_response(self) -> Response {
+        // 本文はリクエストIDが確定するmiddleware::error::error_rendererで生成する
+        let mut response = (self.status_code(), Body::empty()).into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "Error")]
+struct S3ErrorResponse {
+    #[serde(rename = "Code")]
+    code: String,
+    #[serde(rename = "Message")]
+    message: String,
+    #[serde(rename = "Resource")]
+    resource: String,
+    #[serde(rename = "RequestId")]
+    request_id: String,
+}
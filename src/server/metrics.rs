@@ -0,0 +1,213 @@
+use crate::{
+    config,
+    server::{AppResult, error::S3Error, operation::OperationType},
+    storage,
+};
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    http::{Request, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing,
+};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use once_cell::sync::Lazy;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{net::TcpListener, time};
+
+/// オブジェクトの数などを集計する間隔
+/// 集計はテーブル全体を走査するため、取得のたびではなく定期的に行う
+const STATISTICS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    received_bytes: IntCounterVec,
+    sent_bytes: IntCounterVec,
+    signature_failures: IntCounterVec,
+    objects: IntGauge,
+    stored_bytes: IntGauge,
+    multipart_uploads: IntGauge,
+    disk_available_bytes: IntGaugeVec,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(|| {
+    let registry = Registry::new_custom(Some("ofuton".to_string()), None).expect("Failed to create metrics registry");
+
+    let requests = IntCounterVec::new(
+        Opts::new("requests_total", "Number of requests by S3 operation and status"),
+        &["operation", "status"],
+    )
+    .unwrap();
+    let request_duration = HistogramVec::new(
+        HistogramOpts::new("request_duration_seconds", "Time until the response headers are returned"),
+        &["operation", "status"],
+    )
+    .unwrap();
+    let received_bytes = IntCounterVec::new(Opts::new("received_bytes_total", "Bytes of request bodies"), &["operation"]).unwrap();
+    let sent_bytes = IntCounterVec::new(Opts::new("sent_bytes_total", "Bytes of response bodies"), &["operation"]).unwrap();
+    let signature_failures = IntCounterVec::new(
+        Opts::new("signature_failures_total", "Number of requests rejected by signature verification"),
+        &["reason"],
+    )
+    .unwrap();
+    let objects = IntGauge::new("objects", "Number of stored objects").unwrap();
+    let stored_bytes = IntGauge::new("stored_bytes", "Total size of stored objects").unwrap();
+    let multipart_uploads = IntGauge::new("multipart_uploads", "Number of multipart uploads in progress").unwrap();
+    let disk_available_bytes = IntGaugeVec::new(
+        Opts::new("disk_available_bytes", "Free space of the file system where objects are stored"),
+        &["path"],
+    )
+    .unwrap();
+
+    registry.register(Box::new(requests.clone())).unwrap();
+    registry.register(Box::new(request_duration.clone())).unwrap();
+    registry.register(Box::new(received_bytes.clone())).unwrap();
+    registry.register(Box::new(sent_bytes.clone())).unwrap();
+    registry.register(Box::new(signature_failures.clone())).unwrap();
+    registry.register(Box::new(objects.clone())).unwrap();
+    registry.register(Box::new(stored_bytes.clone())).unwrap();
+    registry.register(Box::new(multipart_uploads.clone())).unwrap();
+    registry.register(Box::new(disk_available_bytes.clone())).unwrap();
+
+    Metrics {
+        registry,
+        requests,
+        request_duration,
+        received_bytes,
+        sent_bytes,
+        signature_failures,
+        objects,
+        stored_bytes,
+        multipart_uploads,
+        disk_available_bytes,
+    }
+});
+
+/// `/metrics`を別のリスナーで公開する
+pub fn spawn_listener(conf: &config::CFGMetrics) {
+    spawn_statistics_refresher();
+
+    let addr = format!("{}:{}", conf.host, conf.port);
+    tokio::spawn(async move {
+        let listener = TcpListener::bind(&addr).await;
+        if let Err(err) = listener {
+            tracing::error!("Failed to bind metrics listener to {}: {}", addr, err);
+            return;
+        }
+
+        tracing::info!("Metrics listening on http://{}/metrics", addr);
+
+        let app = Router::new().route("/metrics", routing::get(metrics_handler));
+        let server = axum::serve(listener.unwrap(), app).await;
+        if let Err(err) = server {
+            tracing::error!("Metrics server error: {}", err);
+        }
+    });
+}
+
+/// オブジェクトの数、内容のサイズの合計、進行中のマルチパートアップロードの数を定期的に集計する
+fn spawn_statistics_refresher() {
+    tokio::spawn(async {
+        let mut interval = time::interval(STATISTICS_REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let statistics = storage::get_statistics().await;
+            if let Err(err) = statistics {
+                tracing::warn!("Failed to refresh storage statistics: {}", err);
+                continue;
+            }
+
+            let statistics = statistics.unwrap();
+            METRICS.objects.set(statistics.objects);
+            METRICS.stored_bytes.set(statistics.stored_bytes);
+            METRICS.multipart_uploads.set(statistics.multipart_uploads as i64);
+        }
+    });
+}
+
+/// 操作ごとのリクエスト数、レイテンシ、送受信したバイト数を記録する
+pub async fn request_metrics(request: Request<Body>, next: Next) -> Response {
+    let operation = OperationType::from_request(&request).as_str();
+    let started_at = Instant::now();
+
+    let received_bytes = METRICS.received_bytes.with_label_values(&[operation]);
+    let request = request.map(|body| Body::new(CountingBody::new(body, received_bytes)));
+
+    let response = next.run(request).await;
+    let status = response.status();
+    METRICS.requests.with_label_values(&[operation, status.as_str()]).inc();
+    METRICS
+        .request_duration
+        .with_label_values(&[operation, status.as_str()])
+        .observe(started_at.elapsed().as_secs_f64());
+
+    let sent_bytes = METRICS.sent_bytes.with_label_values(&[operation]);
+    response.map(|body| Body::new(CountingBody::new(body, sent_bytes)))
+}
+
+/// 署名の検証に失敗したリクエストを記録する
+pub fn record_signature_failure(error: &S3Error) {
+    METRICS.signature_failures.with_label_values(&[error.code()]).inc();
+}
+
+async fn metrics_handler() -> AppResult<impl IntoResponse> {
+    // オブジェクトの数などは定期的に集計した値を返し、空き容量のみ取得のたびに確認する
+    for path in config::CONFIG.storage_paths() {
+        match storage::available_space(&path) {
+            Ok(space) => METRICS.disk_available_bytes.with_label_values(&[&path]).set(space as i64),
+            Err(err) => tracing::warn!("Failed to get available space of {}: {}", path, err),
+        }
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&METRICS.registry.gather(), &mut buffer)?;
+
+    Ok(([(header::CONTENT_TYPE, encoder.format_type().to_string())], buffer))
+}
+
+/// 本文を読み進めたバイト数をカウンターに加算する
+/// Content-Lengthが失われないよう、size_hintは元の本文のものを返す
+struct CountingBody {
+    inner: Body,
+    counter: IntCounter,
+}
+
+impl CountingBody {
+    fn new(inner: Body, counter: IntCounter) -> Self {
+        Self { inner, counter }
+    }
+}
+
+impl HttpBody for CountingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame &&
+            let Some(data) = frame.data_ref()
+        {
+            self.counter.inc_by(data.len() as u64);
+        }
+
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
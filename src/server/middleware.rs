@@ -1,3 +1,6 @@
+pub mod bucket;
+pub mod error;
 pub mod logger;
 pub mod multipart;
 pub mod signature;
+pub mod virtual_host;
@@ -0,0 +1,20 @@
+use crate::{config, server::error::S3Error};
+use axum::{
+    body::Body,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// パスの先頭のバケット名から、バケットの設定を解決する
+/// 存在しないバケットへのリクエストにはNoSuchBucketを返す
+pub async fn bucket_resolver(mut request: Request<Body>, next: Next) -> Response {
+    let name = request.uri().path().trim_start_matches('/').split('/').next().unwrap_or_default();
+    let bucket = config::CONFIG.find_bucket(name);
+    if bucket.is_none() {
+        return S3Error::NoSuchBucket.into_response();
+    }
+
+    request.extensions_mut().insert(bucket.unwrap());
+    next.run(request).await
+}
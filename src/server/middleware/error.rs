@@ -0,0 +1,18 @@
+use crate::server::{error::S3Error, utils::get_header};
+use axum::{body::Body, http::Request, middleware::Next, response::Response};
+
+pub async fn error_renderer(request: Request<Body>, next: Next) -> Response {
+    let request_id = get_header(request.headers(), "x-request-id", None);
+    let resource = request.uri().path().to_string();
+
+    let response = next.run(request).await;
+    let error = response.extensions().get::<S3Error>().cloned();
+    if error.is_none() {
+        return response;
+    }
+
+    let (parts, _) = response.into_parts();
+    let mut rendered = error.unwrap().render(&resource, &request_id);
+    rendered.extensions_mut().extend(parts.extensions);
+    rendered
+}
@@ -19,7 +19,13 @@ pub struct MultipartUploadState {
 
 pub async fn multipart_state_manager(Query(params): Query<ReqParams>, mut request: Request<Body>, next: Next) -> Response {
     let upload_id = params.upload_id.clone();
-    let is_registered = upload_id.is_some() && storage::MULTIPART_UPLOAD_STATE.lock().unwrap().contains_key(upload_id.as_ref().unwrap());
+    // 別のキーで作成されたアップロードは操作できないようにする
+    let is_registered = match upload_id.as_ref() {
+        Some(upload_id) => storage::get_multipart_upload(upload_id)
+            .await
+            .is_some_and(|upload| upload.path == request.uri().path()),
+        None => false,
+    };
 
     let state = MultipartUploadState {
         is_registered,
@@ -1,10 +1,13 @@
-use crate::{config, server::utils::get_header};
+use crate::{
+    config::{self, AppConfig, BucketConfig, CFGCredential, CFGPermission},
+    server::{error::S3Error, metrics, utils::get_header},
+};
 use axum::{
     body::Body,
-    extract::State,
+    extract::{OriginalUri, State},
     http::{Request, Uri},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
 use chrono::{NaiveDateTime, Utc};
 use hmac::{Hmac, Mac};
@@ -13,86 +16,343 @@ use std::{collections::HashMap, sync::Arc};
 
 #[derive(Clone)]
 pub struct SignatureVerificationState {
-    pub key: Arc<String>,
-    pub secret: Arc<String>,
+    credentials: Arc<HashMap<String, Arc<CFGCredential>>>,
 }
 
-type HmacSha256 = Hmac<Sha256>;
+impl SignatureVerificationState {
+    pub fn new(conf: &AppConfig) -> Self {
+        // `[account]`のアクセスキーは全ての操作を許可する
+        let account = CFGCredential {
+            access_key: conf.account.access_key.clone(),
+            secret_key: conf.account.secret_key.clone(),
+            permissions: vec![CFGPermission::Read, CFGPermission::Write, CFGPermission::Delete],
+            prefixes: vec![],
+        };
+
+        let mut credentials = HashMap::new();
+        for credential in std::iter::once(account).chain(conf.credentials.iter().cloned()) {
+            if credentials.contains_key(&credential.access_key) {
+                tracing::warn!("Duplicate access key '{}' is ignored", credential.access_key);
+                continue;
+            }
+
+            credentials.insert(credential.access_key.clone(), Arc::new(credential));
+        }
 
-pub async fn signature_verification(State(signatures): State<SignatureVerificationState>, request: Request<Body>, next: Next) -> Response {
-    if !internal_verify(&request, &signatures.key, &signatures.secret) {
-        return Response::builder().status(403).body(Body::from("Forbidden: Invalid signature")).unwrap();
+        Self {
+            credentials: Arc::new(credentials),
+        }
     }
+}
 
-    next.run(request).await
+/// 検証に成功したリクエストの署名情報
+/// aws-chunkedで送られてきたペイロードのチャンク署名を検証する際に使用する
+#[derive(Clone, Debug)]
+pub struct SignatureContext {
+    pub content_hash: String,
+    pub seed_signature: String,
+    amz_date: String,
+    credentials_scope: String,
+    signing_key: Vec<u8>,
+    credential: Arc<CFGCredential>,
 }
 
-fn internal_verify(request: &Request<Body>, access_key: &str, secret_key: &str) -> bool {
-    let authorization = get_header(request.headers(), "Authorization", None);
-    if authorization.is_empty() {
-        tracing::debug!("SignatureVerification Failed: Authorization header is missing or empty");
-        return false;
+impl SignatureContext {
+    /// 署名に使用したアクセスキーが、パス (`bucket/key`) への操作を許可されているかを確認する
+    pub fn authorize(&self, permission: CFGPermission, path: &str) -> Result<(), S3Error> {
+        let credential = &self.credential;
+        let is_permitted = credential.permissions.contains(&permission);
+        let is_in_prefixes = credential.prefixes.is_empty() || credential.prefixes.iter().any(|prefix| path.starts_with(prefix.as_str()));
+        if !is_permitted || !is_in_prefixes {
+            tracing::debug!("Access key '{}' is not allowed to {:?} '{}'", credential.access_key, permission, path);
+            return Err(S3Error::AccessDenied);
+        }
+
+        Ok(())
     }
 
-    let components = get_components(authorization.as_str());
-    let signature = components.get("Signature").unwrap_or(&"");
-    let credentials = components.get("Credential").unwrap_or(&"").split("/").collect::<Vec<&str>>();
+    /// 署名に使用したアクセスキーが、バケット内のいずれかのパスへの操作を許可されているかを返す
+    pub fn can_access_bucket(&self, bucket: &str) -> bool {
+        let bucket_path = format!("{bucket}/");
+        self.credential.prefixes.is_empty() ||
+            self.credential
+                .prefixes
+                .iter()
+                .any(|prefix| bucket_path.starts_with(prefix.as_str()) || prefix.starts_with(&bucket_path))
+    }
 
-    if signature.is_empty() || credentials.len() != 5 {
-        tracing::debug!("SignatureVerification Failed: Invalid signature or credentials length mismatch");
-        return false;
+    pub fn sign_chunk(&self, previous_signature: &str, chunk: &[u8]) -> String {
+        let string_to_sign = [
+            "AWS4-HMAC-SHA256-PAYLOAD",
+            self.amz_date.as_str(),
+            self.credentials_scope.as_str(),
+            previous_signature,
+            EMPTY_SHA256,
+            sha256_hex(chunk).as_str(),
+        ]
+        .join("\n");
+
+        hmac_sha256_hex(&self.signing_key, string_to_sign.as_bytes())
     }
 
-    if credentials[0] != access_key {
-        tracing::debug!("SignatureVerification Failed: Access key mismatch");
-        return false;
+    pub fn sign_trailer(&self, previous_signature: &str, trailer: &[u8]) -> String {
+        let string_to_sign = [
+            "AWS4-HMAC-SHA256-TRAILER",
+            self.amz_date.as_str(),
+            self.credentials_scope.as_str(),
+            previous_signature,
+            sha256_hex(trailer).as_str(),
+        ]
+        .join("\n");
+
+        hmac_sha256_hex(&self.signing_key, string_to_sign.as_bytes())
     }
+}
 
-    let sigined_datetime = NaiveDateTime::parse_from_str(&get_header(request.headers(), "X-Amz-Date", None), "%Y%m%dT%H%M%SZ");
-    if sigined_datetime.is_err() ||
-        (Utc::now().naive_utc() - sigined_datetime.unwrap()).num_seconds() > config::CONFIG.bucket.request_expiration_seconds
-    {
-        tracing::debug!("SignatureVerification Failed: Signature date is invalid or expires");
-        tracing::debug!("Err: {}", sigined_datetime.unwrap_err());
-        return false;
+#[cfg(test)]
+impl SignatureContext {
+    /// シード署名と署名に使用した情報から直接作成する
+    pub fn new_for_test(content_hash: &str, seed_signature: &str, amz_date: &str, credentials_scope: &str, secret_key: &str) -> Self {
+        let scope = credentials_scope.split('/').collect::<Vec<&str>>();
+        Self {
+            content_hash: content_hash.to_string(),
+            seed_signature: seed_signature.to_string(),
+            amz_date: amz_date.to_string(),
+            credentials_scope: credentials_scope.to_string(),
+            signing_key: derive_signing_key(secret_key, &scope),
+            credential: Arc::new(CFGCredential {
+                access_key: String::new(),
+                secret_key: secret_key.to_string(),
+                permissions: Vec::new(),
+                prefixes: Vec::new(),
+            }),
+        }
     }
+}
 
-    let mut signed_headers = components.get("SignedHeaders").unwrap_or(&"").split(';').collect::<Vec<&str>>();
-    signed_headers.sort();
+type HmacSha256 = Hmac<Sha256>;
+
+const SIGNATURE_ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+/// 署名付きURLの有効期限の上限 (7日間)
+const PRESIGNED_URL_MAX_EXPIRES: i64 = 604800;
+/// 署名日時がサーバーの時刻より未来であることを許容する範囲 (15分間)
+const MAX_CLOCK_SKEW_SECONDS: i64 = 900;
+
+/// Authorizationヘッダーまたはクエリパラメータから取り出した署名情報
+#[derive(Debug)]
+struct SignatureComponents {
+    credentials: Vec<String>,
+    signed_headers: Vec<String>,
+    signature: String,
+    amz_date: String,
+    content_hash: String,
+    expires: Option<i64>,
+}
+
+pub async fn signature_verification(State(signatures): State<SignatureVerificationState>, mut request: Request<Body>, next: Next) -> Response {
+    let context = internal_verify(&request, &signatures.credentials);
+    if let Err(e) = context {
+        metrics::record_signature_failure(&e);
+        return e.into_response();
+    }
+
+    request.extensions_mut().insert(context.unwrap());
+    next.run(request).await
+}
 
-    let string_to_sign = get_string_to_sign(request, &credentials, &signed_headers);
+/// 署名が含まれている場合のみ検証を行う
+/// 匿名での読み取りを許可しつつ、署名付きURLの期限切れや改ざんを検出するために使用する
+pub async fn optional_signature_verification(
+    State(signatures): State<SignatureVerificationState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    if is_signed_request(&request) {
+        let context = internal_verify(&request, &signatures.credentials);
+        if let Err(e) = context {
+            metrics::record_signature_failure(&e);
+            return e.into_response();
+        }
 
-    let mut mac = HmacSha256::new_from_slice(format!("AWS4{secret_key}").as_bytes()).unwrap();
-    mac.update(credentials[1].as_bytes()); // Date
-    let date_key = mac.finalize().into_bytes();
+        request.extensions_mut().insert(context.unwrap());
+    }
 
-    let mut mac = HmacSha256::new_from_slice(&date_key).unwrap();
-    mac.update(credentials[2].as_bytes()); // Region
-    let region_key = mac.finalize().into_bytes();
+    next.run(request).await
+}
 
-    let mut mac = HmacSha256::new_from_slice(&region_key).unwrap();
-    mac.update(credentials[3].as_bytes()); // Service
-    let service_key = mac.finalize().into_bytes();
+fn is_signed_request(request: &Request<Body>) -> bool {
+    request.headers().contains_key("Authorization") || get_query_params(request.uri()).contains_key("X-Amz-Signature")
+}
 
-    let mut mac = HmacSha256::new_from_slice(&service_key).unwrap();
-    mac.update(credentials[4].as_bytes()); // "aws4_request"
-    let signing_key = mac.finalize().into_bytes();
+fn internal_verify(request: &Request<Body>, access_keys: &HashMap<String, Arc<CFGCredential>>) -> Result<SignatureContext, S3Error> {
+    let query_params = get_query_params(request.uri());
+    let components = if query_params.contains_key("X-Amz-Signature") {
+        get_query_components(&query_params)?
+    } else {
+        get_header_components(request)?
+    };
 
-    let mut mac = HmacSha256::new_from_slice(&signing_key).unwrap();
-    mac.update(string_to_sign.as_bytes());
-    let signature_bytes = mac.finalize().into_bytes();
+    let credentials = &components.credentials;
+    let credential = access_keys.get(&credentials[0]);
+    if credential.is_none() {
+        tracing::debug!("SignatureVerification Failed: Access key mismatch");
+        return Err(S3Error::InvalidAccessKeyId);
+    }
+    let credential = credential.unwrap().clone();
+
+    let sigined_datetime = NaiveDateTime::parse_from_str(&components.amz_date, "%Y%m%dT%H%M%SZ");
+    if let Err(e) = sigined_datetime {
+        tracing::debug!("SignatureVerification Failed: Signature date is invalid: {}", e);
+        return Err(S3Error::AccessDenied);
+    }
+
+    let elapsed_seconds = (Utc::now().naive_utc() - sigined_datetime.unwrap()).num_seconds();
+    match components.expires {
+        Some(expires) => {
+            // 未来の日時で署名されたURLは、有効期限の上限を超えて使用できてしまうため拒否する
+            if elapsed_seconds < -MAX_CLOCK_SKEW_SECONDS {
+                tracing::debug!("SignatureVerification Failed: Presigned URL is dated in the future");
+                return Err(S3Error::AccessDenied);
+            }
+
+            if elapsed_seconds > expires {
+                tracing::debug!("SignatureVerification Failed: Presigned URL expires");
+                return Err(S3Error::AccessDenied);
+            }
+        }
+        None => {
+            // バケットごとに設定された有効期限を使用する
+            let request_expiration_seconds = request.extensions().get::<BucketConfig>().map_or_else(
+                || config::CONFIG.bucket.request_expiration_seconds,
+                |bucket| bucket.request_expiration_seconds,
+            );
+
+            if elapsed_seconds < -MAX_CLOCK_SKEW_SECONDS {
+                tracing::debug!("SignatureVerification Failed: Signature date is in the future");
+                return Err(S3Error::RequestTimeTooSkewed);
+            }
+
+            if elapsed_seconds > request_expiration_seconds {
+                tracing::debug!("SignatureVerification Failed: Signature date expires");
+                return Err(S3Error::RequestTimeTooSkewed);
+            }
+        }
+    }
 
-    let calculated_signature = format!("{signature_bytes:x}");
-    let verify_result = calculated_signature == *signature;
-    if !verify_result {
+    let credentials_scope = credentials[1..].join("/"); // Date/Region/Service/"aws4_request"
+    let string_to_sign = get_string_to_sign(request, &components, &credentials_scope);
+
+    let signing_key = derive_signing_key(&credential.secret_key, &credentials[1..]);
+    let calculated_signature = hmac_sha256_hex(&signing_key, string_to_sign.as_bytes());
+    if calculated_signature != components.signature {
         tracing::debug!(
             "SignatureVerification Failed: Signature mismatch. Expected: {}, Got: {}",
             calculated_signature,
-            signature
+            components.signature
         );
+        return Err(S3Error::SignatureDoesNotMatch);
     }
 
-    verify_result
+    Ok(SignatureContext {
+        content_hash: components.content_hash,
+        seed_signature: calculated_signature,
+        amz_date: components.amz_date,
+        credentials_scope,
+        signing_key,
+        credential,
+    })
+}
+
+/// シークレットキーとスコープ (Date/Region/Service/"aws4_request") から署名鍵を導出する
+fn derive_signing_key<S: AsRef<str>>(secret_key: &str, scope: &[S]) -> Vec<u8> {
+    let mut key = format!("AWS4{secret_key}").into_bytes();
+    for component in scope {
+        let mut mac = HmacSha256::new_from_slice(&key).unwrap();
+        mac.update(component.as_ref().as_bytes());
+        key = mac.finalize().into_bytes().to_vec();
+    }
+
+    key
+}
+
+fn hmac_sha256_hex(key: &[u8], data: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(key).unwrap();
+    mac.update(data);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+fn get_header_components(request: &Request<Body>) -> Result<SignatureComponents, S3Error> {
+    let authorization = get_header(request.headers(), "Authorization", None);
+    if authorization.is_empty() {
+        tracing::debug!("SignatureVerification Failed: Authorization header is missing or empty");
+        return Err(S3Error::AccessDenied);
+    }
+
+    let components = get_components(authorization.as_str());
+    let signature = components.get("Signature").unwrap_or(&"");
+    let credentials = components.get("Credential").unwrap_or(&"").split("/").collect::<Vec<&str>>();
+
+    if signature.is_empty() || credentials.len() != 5 {
+        tracing::debug!("SignatureVerification Failed: Invalid signature or credentials length mismatch");
+        return Err(S3Error::AccessDenied);
+    }
+
+    Ok(SignatureComponents {
+        credentials: credentials.iter().map(|s| s.to_string()).collect(),
+        signed_headers: get_signed_headers(components.get("SignedHeaders").unwrap_or(&"")),
+        signature: signature.to_string(),
+        amz_date: get_header(request.headers(), "X-Amz-Date", None),
+        content_hash: get_header(request.headers(), "X-Amz-Content-Sha256", Some(UNSIGNED_PAYLOAD.to_string())),
+        expires: None,
+    })
+}
+
+fn get_query_components(query_params: &HashMap<String, String>) -> Result<SignatureComponents, S3Error> {
+    let get_param = |key: &str| query_params.get(key).cloned().unwrap_or_default();
+
+    if get_param("X-Amz-Algorithm") != SIGNATURE_ALGORITHM {
+        tracing::debug!("SignatureVerification Failed: Unsupported signature algorithm");
+        return Err(S3Error::AccessDenied);
+    }
+
+    let signature = get_param("X-Amz-Signature");
+    let credentials = get_param("X-Amz-Credential").split("/").map(|s| s.to_string()).collect::<Vec<String>>();
+    if signature.is_empty() || credentials.len() != 5 {
+        tracing::debug!("SignatureVerification Failed: Invalid signature or credentials length mismatch");
+        return Err(S3Error::AccessDenied);
+    }
+
+    let expires = get_param("X-Amz-Expires").parse::<i64>();
+    if let Err(e) = expires {
+        tracing::debug!("SignatureVerification Failed: X-Amz-Expires is invalid: {}", e);
+        return Err(S3Error::AccessDenied);
+    }
+
+    let expires = expires.unwrap();
+    if !(1..=PRESIGNED_URL_MAX_EXPIRES).contains(&expires) {
+        tracing::debug!("SignatureVerification Failed: X-Amz-Expires is out of range: {}", expires);
+        return Err(S3Error::AuthorizationQueryParametersError(format!(
+            "X-Amz-Expires must be between 1 and {PRESIGNED_URL_MAX_EXPIRES} seconds"
+        )));
+    }
+
+    Ok(SignatureComponents {
+        credentials,
+        signed_headers: get_signed_headers(&get_param("X-Amz-SignedHeaders")),
+        signature,
+        amz_date: get_param("X-Amz-Date"),
+        // 署名付きURLではペイロードのハッシュが分からないため、明示されていない限りUNSIGNED-PAYLOADとして扱う
+        content_hash: query_params.get("X-Amz-Content-Sha256").cloned().unwrap_or(UNSIGNED_PAYLOAD.to_string()),
+        expires: Some(expires),
+    })
 }
 
 fn get_components(authorization: &str) -> HashMap<&str, &str> {
@@ -105,63 +365,159 @@ fn get_components(authorization: &str) -> HashMap<&str, &str> {
     components_str.split(',').filter_map(|s| s.trim().split_once('=')).collect()
 }
 
+fn get_signed_headers(signed_headers: &str) -> Vec<String> {
+    let mut signed_headers = signed_headers.split(';').map(|h| h.to_lowercase()).collect::<Vec<String>>();
+    signed_headers.sort();
+    signed_headers
+}
+
+fn get_query_params(uri: &Uri) -> HashMap<String, String> {
+    uri.query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.split_once('=').unwrap_or((s, "")))
+        .map(|(k, v)| (url_decode(k), url_decode(v)))
+        .collect()
+}
+
 fn get_query_string(uri: &Uri) -> String {
     let mut pairs = uri
         .query()
         .unwrap_or("")
         .split('&')
+        .filter(|s| !s.is_empty())
         .map(|s| s.split_once('=').unwrap_or((s, "")))
         .filter(|(k, _)| *k != "X-Amz-Signature")
-        .map(|(k, v)| format!("{}={}", urlencoding::encode(k), urlencoding::encode(v)))
-        .collect::<Vec<String>>();
+        .map(|(k, v)| (canonical_uri_encode(k), canonical_uri_encode(v)))
+        .collect::<Vec<(String, String)>>();
 
     pairs.sort();
-    pairs.join("&")
+    pairs.iter().map(|(k, v)| format!("{k}={v}")).collect::<Vec<String>>().join("&")
+}
+
+fn url_decode(value: &str) -> String {
+    urlencoding::decode(value).map(|v| v.into_owned()).unwrap_or(value.to_string())
+}
+
+fn canonical_uri_encode(value: &str) -> String {
+    // クエリは既にエンコードされているため、一度デコードしてからエンコードし直す
+    urlencoding::encode(&url_decode(value)).to_string()
 }
 
-fn get_string_to_sign(request: &Request<Body>, credentials: &[&str], signed_headers: &Vec<&str>) -> String {
-    let canonical_headers = signed_headers
+fn get_string_to_sign(request: &Request<Body>, components: &SignatureComponents, credentials_scope: &str) -> String {
+    let canonical_headers = components
+        .signed_headers
         .iter()
         .map(|h| {
             let header_value = get_header(request.headers(), h, None);
-            format!("{}:{}\n", h.to_lowercase(), header_value)
+            format!("{}:{}\n", h, header_value)
         })
         .collect::<String>();
 
-    let content_hash = get_header(request.headers(), "X-Amz-Content-Sha256", Some("UNSIGNED-PAYLOAD".to_string()));
+    // バーチャルホスト形式のリクエストは、パス形式に書き換える前のパスに署名されている
+    let canonical_uri = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(request.uri().path(), |OriginalUri(uri)| uri.path());
 
     let canonical_request_string = [
         request.method().as_str(),
-        request.uri().path(),
+        canonical_uri,
         &get_query_string(request.uri()),
         canonical_headers.as_str(),
-        signed_headers
-            .iter()
-            .map(|h| h.to_lowercase())
-            .collect::<Vec<String>>()
-            .join(";")
-            .as_str(),
-        content_hash.as_str(),
+        components.signed_headers.join(";").as_str(),
+        components.content_hash.as_str(),
     ]
     .join("\n");
 
-    let mut hasher = Sha256::new();
-    hasher.update(canonical_request_string.as_bytes());
-    let canonical_request_hash = format!("{:x}", hasher.finalize());
-
-    let credentials_scope = [
-        credentials[1], // Date
-        credentials[2], // Region
-        credentials[3], // Service
-        credentials[4], // "aws4_request"
-    ]
-    .join("/");
+    let canonical_request_hash = sha256_hex(canonical_request_string.as_bytes());
 
     [
-        "AWS4-HMAC-SHA256",
-        get_header(request.headers(), "X-Amz-Date", None).as_str(),
-        credentials_scope.as_str(),
+        SIGNATURE_ALGORITHM,
+        components.amz_date.as_str(),
+        credentials_scope,
         canonical_request_hash.as_str(),
     ]
     .join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const ACCESS_KEY: &str = "test-access-key";
+    const SECRET_KEY: &str = "test-secret-key";
+
+    fn access_keys() -> HashMap<String, Arc<CFGCredential>> {
+        let credential = CFGCredential {
+            access_key: ACCESS_KEY.to_string(),
+            secret_key: SECRET_KEY.to_string(),
+            permissions: vec![CFGPermission::Read],
+            prefixes: vec![],
+        };
+        HashMap::from([(ACCESS_KEY.to_string(), Arc::new(credential))])
+    }
+
+    /// `signed_at`に署名した、`expires`秒間有効な署名付きURLのリクエストを作成する
+    fn presigned_request(signed_at: NaiveDateTime, expires: i64) -> Request<Body> {
+        let amz_date = signed_at.format("%Y%m%dT%H%M%SZ").to_string();
+        let scope = [
+            signed_at.format("%Y%m%d").to_string(),
+            "us-east-1".to_string(),
+            "s3".to_string(),
+            "aws4_request".to_string(),
+        ];
+        let query = format!(
+            "X-Amz-Algorithm={SIGNATURE_ALGORITHM}&X-Amz-Credential={}&X-Amz-Date={amz_date}&X-Amz-Expires={expires}&X-Amz-SignedHeaders=host",
+            urlencoding::encode(&format!("{ACCESS_KEY}/{}", scope.join("/")))
+        );
+        let build = |query: &str| {
+            Request::builder()
+                .uri(format!("/bucket/key?{query}"))
+                .header("Host", "localhost")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // 署名を除いた部分から署名を計算し、クエリに追加する
+        let unsigned = build(&format!("{query}&X-Amz-Signature=unsigned"));
+        let components = get_query_components(&get_query_params(unsigned.uri())).unwrap();
+        let string_to_sign = get_string_to_sign(&unsigned, &components, &scope.join("/"));
+        let signature = hmac_sha256_hex(&derive_signing_key(SECRET_KEY, &scope), string_to_sign.as_bytes());
+
+        build(&format!("{query}&X-Amz-Signature={signature}"))
+    }
+
+    #[test]
+    fn accepts_valid_presigned_url() {
+        let request = presigned_request(Utc::now().naive_utc() - Duration::seconds(60), 3600);
+        assert!(internal_verify(&request, &access_keys()).is_ok());
+    }
+
+    #[test]
+    fn rejects_tampered_presigned_url() {
+        let request = presigned_request(Utc::now().naive_utc(), 3600);
+        let uri = request.uri().to_string().replace("/bucket/key", "/bucket/other");
+        let request = Request::builder().uri(uri).header("Host", "localhost").body(Body::empty()).unwrap();
+        assert!(matches!(internal_verify(&request, &access_keys()), Err(S3Error::SignatureDoesNotMatch)));
+    }
+
+    #[test]
+    fn rejects_expired_presigned_url() {
+        let request = presigned_request(Utc::now().naive_utc() - Duration::seconds(3601), 3600);
+        assert!(matches!(internal_verify(&request, &access_keys()), Err(S3Error::AccessDenied)));
+    }
+
+    #[test]
+    fn rejects_future_dated_presigned_url() {
+        // 有効期限の上限を超えて使用できないよう、未来の日時で署名されたURLは拒否する
+        let request = presigned_request(Utc::now().naive_utc() + Duration::days(30), PRESIGNED_URL_MAX_EXPIRES);
+        assert!(matches!(internal_verify(&request, &access_keys()), Err(S3Error::AccessDenied)));
+
+        // 時計のずれの範囲内であれば受け付ける
+        let request = presigned_request(Utc::now().naive_utc() + Duration::seconds(60), 3600);
+        assert!(internal_verify(&request, &access_keys()).is_ok());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::config;
+use axum::{
+    extract::OriginalUri,
+    http::{Request, Uri, header},
+};
+
+/// バーチャルホスト形式 (`bucket.s3.example.com/key`) のリクエストを、パス形式 (`/bucket/key`) に書き換える
+/// 署名の検証ではクライアントが署名したパスを使うため、書き換える前のURIを`OriginalUri`として残す
+/// ルーティングより前に実行する必要があるため、Routerの外側から`MapRequestLayer`で適用する
+pub fn virtual_host_resolver<B>(mut request: Request<B>) -> Request<B> {
+    let bucket = get_host(&request).and_then(|host| get_bucket_from_host(&host));
+    if let Some(bucket) = bucket {
+        let original_uri = request.uri().clone();
+        let path_and_query = match original_uri.query() {
+            Some(query) => format!("/{bucket}{}?{query}", original_uri.path()),
+            None => format!("/{bucket}{}", original_uri.path()),
+        };
+
+        let mut parts = original_uri.clone().into_parts();
+        parts.path_and_query = path_and_query.parse().ok();
+        if let Ok(uri) = Uri::from_parts(parts) {
+            request.extensions_mut().insert(OriginalUri(original_uri));
+            *request.uri_mut() = uri;
+        }
+    }
+
+    request
+}
+
+fn get_host<B>(request: &Request<B>) -> Option<String> {
+    // HTTP/2ではHostヘッダーの代わりに:authorityが使われる
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or(request.uri().host())?;
+
+    // ポート番号を取り除く
+    let host = host.rsplit_once(':').map_or(host, |(host, _)| host);
+    Some(host.to_ascii_lowercase())
+}
+
+/// Hostが`{bucket}.{base_domain}`の場合にバケット名を返す
+fn get_bucket_from_host(host: &str) -> Option<String> {
+    let base_domain = config::CONFIG.server.base_domain.trim_matches('.').to_ascii_lowercase();
+    if base_domain.is_empty() {
+        return None;
+    }
+
+    host.strip_suffix(&base_domain)
+        .and_then(|bucket| bucket.strip_suffix('.'))
+        .filter(|bucket| !bucket.is_empty())
+        .map(|bucket| bucket.to_string())
+}
@@ -0,0 +1,106 @@
+use axum::{
+    extract::MatchedPath,
+    http::{Method, Request},
+};
+use std::collections::HashSet;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum OperationType {
+    ListBuckets,
+    HeadBucket,
+    ListObjects,
+    ListObjectsV2,
+    ListMultipartUploads,
+    DeleteObjects,
+    GetObject,
+    HeadObject,
+    ListParts,
+    PutObject,
+    CopyObject,
+    CreateMultipartUpload,
+    UploadPart,
+    UploadPartCopy,
+    CompleteMultipartUpload,
+    AbortMultipartUpload,
+    DeleteObject,
+    Unknown,
+}
+
+impl OperationType {
+    /// マッチしたルートとメソッド、クエリパラメータからS3の操作を判別する
+    /// ルートにマッチしなかったリクエストや、S3の操作ではないリクエストは`Unknown`となる
+    pub fn from_request<B>(request: &Request<B>) -> Self {
+        let matched_path = request.extensions().get::<MatchedPath>().map(|path| path.as_str());
+        let query = request.uri().query().unwrap_or_default();
+        let params = url::form_urlencoded::parse(query.as_bytes())
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect::<Vec<_>>();
+        let keys = params.iter().map(|(key, _)| key.as_str()).collect::<HashSet<_>>();
+        let method = request.method();
+
+        match matched_path {
+            Some("/") => {
+                // 署名されていないリクエストにはインデックスページを返している
+                let is_signed = request.headers().contains_key("Authorization") || keys.contains("X-Amz-Signature");
+                if method == Method::GET && is_signed {
+                    OperationType::ListBuckets
+                } else {
+                    OperationType::Unknown
+                }
+            }
+            Some("/{bucket}") | Some("/{bucket}/") => match *method {
+                Method::GET if keys.contains("uploads") => OperationType::ListMultipartUploads,
+                Method::GET if params.iter().any(|(key, value)| key == "list-type" && value == "2") => OperationType::ListObjectsV2,
+                Method::GET => OperationType::ListObjects,
+                Method::HEAD => OperationType::HeadBucket,
+                Method::POST if keys.contains("delete") => OperationType::DeleteObjects,
+                _ => OperationType::Unknown,
+            },
+            Some("/{bucket}/{*object}") => {
+                let is_multipart_operation = keys.contains("uploadId");
+                let is_copy_operation = request.headers().get("X-Amz-Copy-Source").is_some_and(|value| !value.is_empty());
+
+                match *method {
+                    Method::GET if is_multipart_operation => OperationType::ListParts,
+                    Method::GET => OperationType::GetObject,
+                    Method::HEAD => OperationType::HeadObject,
+                    Method::PUT => match (is_multipart_operation, is_copy_operation) {
+                        (true, true) => OperationType::UploadPartCopy,
+                        (true, false) => OperationType::UploadPart,
+                        (false, true) => OperationType::CopyObject,
+                        (false, false) => OperationType::PutObject,
+                    },
+                    Method::POST if is_multipart_operation => OperationType::CompleteMultipartUpload,
+                    Method::POST => OperationType::CreateMultipartUpload,
+                    Method::DELETE if is_multipart_operation => OperationType::AbortMultipartUpload,
+                    Method::DELETE => OperationType::DeleteObject,
+                    _ => OperationType::Unknown,
+                }
+            }
+            _ => OperationType::Unknown,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperationType::ListBuckets => "ListBuckets",
+            OperationType::HeadBucket => "HeadBucket",
+            OperationType::ListObjects => "ListObjects",
+            OperationType::ListObjectsV2 => "ListObjectsV2",
+            OperationType::ListMultipartUploads => "ListMultipartUploads",
+            OperationType::DeleteObjects => "DeleteObjects",
+            OperationType::GetObject => "GetObject",
+            OperationType::HeadObject => "HeadObject",
+            OperationType::ListParts => "ListParts",
+            OperationType::PutObject => "PutObject",
+            OperationType::CopyObject => "CopyObject",
+            OperationType::CreateMultipartUpload => "CreateMultipartUpload",
+            OperationType::UploadPart => "UploadPart",
+            OperationType::UploadPartCopy => "UploadPartCopy",
+            OperationType::CompleteMultipartUpload => "CompleteMultipartUpload",
+            OperationType::AbortMultipartUpload => "AbortMultipartUpload",
+            OperationType::DeleteObject => "DeleteObject",
+            OperationType::Unknown => "Unknown",
+        }
+    }
+}
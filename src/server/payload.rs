@@ -0,0 +1,699 @@
+use crate::{
+    server::{error::S3Error, metrics, middleware::signature::SignatureContext, utils::get_header},
+    storage::ObjectStream,
+};
+use anyhow::Error;
+use axum::{
+    body::{Body, BodyDataStream},
+    http::HeaderMap,
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use bytes::{Buf, Bytes, BytesMut};
+use crc::{CRC_32_ISCSI, CRC_32_ISO_HDLC, CRC_64_NVME, Crc};
+use futures::stream;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tokio_stream::StreamExt;
+
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const STREAMING_UNSIGNED_PAYLOAD_TRAILER: &str = "STREAMING-UNSIGNED-PAYLOAD-TRAILER";
+const STREAMING_SIGNED_PAYLOAD: &str = "STREAMING-AWS4-HMAC-SHA256-PAYLOAD";
+const STREAMING_SIGNED_PAYLOAD_TRAILER: &str = "STREAMING-AWS4-HMAC-SHA256-PAYLOAD-TRAILER";
+
+/// チャンクヘッダーの1行あたりの最大長、およびトレーラー全体の最大長
+const MAX_LINE_LENGTH: usize = 4096;
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+static CRC32C: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+static CRC64NVME: Crc<u64> = Crc::<u64>::new(&CRC_64_NVME);
+
+/// リクエストボディを、`X-Amz-Content-Sha256`の内容に応じて検証・デコードするストリームに変換する
+/// 検証に失敗した場合はストリームがエラーを返すため、書き込み側で中途半端なファイルを削除する必要がある
+/// デコード後のサイズが`limit`バイトを超えた時点でEntityTooLargeを返す
+/// aws-chunkedの場合は、上限を超えるチャンクをバッファに読み込む前に拒否する
+/// トレーラー付きの場合は、`X-Amz-Trailer`で宣言されたチェックサムをデコードしたデータと比較する
+pub fn verify_payload(body: Body, context: Option<&SignatureContext>, headers: &HeaderMap, limit: u64) -> Result<ObjectStream, S3Error> {
+    let inner = body.into_data_stream();
+    // aws-chunkedの場合、Content-Lengthはエンコード後のサイズになる
+    let decoded_content_length = get_header(headers, "X-Amz-Decoded-Content-Length", None).parse::<u64>().ok();
+    let context = match context {
+        Some(context) => context.clone(),
+        None => return Ok(limit_payload(passthrough(inner), limit)),
+    };
+
+    match context.content_hash.as_str() {
+        UNSIGNED_PAYLOAD => Ok(limit_payload(passthrough(inner), limit)),
+        STREAMING_UNSIGNED_PAYLOAD_TRAILER => {
+            let checksum = trailer_checksum(headers)?;
+            Ok(decode_chunked(inner, None, true, checksum, decoded_content_length, limit))
+        }
+        STREAMING_SIGNED_PAYLOAD => Ok(decode_chunked(inner, Some(context), false, None, decoded_content_length, limit)),
+        STREAMING_SIGNED_PAYLOAD_TRAILER => {
+            let checksum = trailer_checksum(headers)?;
+            Ok(decode_chunked(inner, Some(context), true, checksum, decoded_content_length, limit))
+        }
+        content_hash => {
+            if content_hash.len() != 64 || !content_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(S3Error::InvalidArgument(
+                    "x-amz-content-sha256 must be UNSIGNED-PAYLOAD, STREAMING-* or a valid sha256 value.".to_string(),
+                ));
+            }
+
+            Ok(limit_payload(verify_sha256(inner, content_hash.to_lowercase()), limit))
+        }
+    }
+}
+
+/// XMLのリクエストボディを検証しながら読み込む
+/// `limit`を超えるサイズのボディはMalformedXMLとして扱う
+pub async fn read_xml_payload(body: Body, context: Option<&SignatureContext>, headers: &HeaderMap, limit: usize) -> Result<Vec<u8>, Error> {
+    let mut stream = verify_payload(body, context, headers, limit as u64)?;
+    let mut payload = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) => payload.extend_from_slice(&chunk),
+            Err(e) if e.downcast_ref::<S3Error>() == Some(&S3Error::EntityTooLarge) => return Err(S3Error::MalformedXML.into()),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(payload)
+}
+
+/// ボディのサイズを`limit`バイトまでに制限し、超えた時点でEntityTooLargeを返すストリームに変換する
+/// Content-Lengthを送らないリクエストや、ヘッダーより大きいボディを送るリクエストにも上限を適用する
+fn limit_payload(inner: ObjectStream, limit: u64) -> ObjectStream {
+    Box::pin(stream::try_unfold((inner, 0), move |(mut inner, size)| async move {
+        match inner.next().await {
+            Some(chunk) => {
+                let chunk = chunk?;
+                let size = size + chunk.len() as u64;
+                if size > limit {
+                    return Err(S3Error::EntityTooLarge.into());
+                }
+
+                Ok(Some((chunk, (inner, size))))
+            }
+            None => Ok(None),
+        }
+    }))
+}
+
+fn passthrough(inner: BodyDataStream) -> ObjectStream {
+    Box::pin(inner.map(|chunk| chunk.map_err(Error::from)))
+}
+
+fn verify_sha256(inner: BodyDataStream, expected_hash: String) -> ObjectStream {
+    let state = (inner, Sha256::new(), expected_hash);
+    Box::pin(stream::try_unfold(state, |(mut inner, mut hasher, expected_hash)| async move {
+        match inner.next().await {
+            Some(chunk) => {
+                let chunk = chunk?;
+                hasher.update(&chunk);
+                Ok(Some((chunk, (inner, hasher, expected_hash))))
+            }
+            None => {
+                let calculated_hash = format!("{:x}", hasher.finalize_reset());
+                if calculated_hash != expected_hash {
+                    tracing::debug!("Payload hash mismatch. Expected: {}, Got: {}", expected_hash, calculated_hash);
+                    return Err(S3Error::XAmzContentSHA256Mismatch.into());
+                }
+
+                Ok(None)
+            }
+        }
+    }))
+}
+
+/// `X-Amz-Trailer`で宣言されたチェックサムの計算を準備する
+/// チェックサム以外のトレーラーが宣言されている場合や、宣言がない場合はNoneを返す
+fn trailer_checksum(headers: &HeaderMap) -> Result<Option<TrailerChecksum>, S3Error> {
+    let trailer = get_header(headers, "X-Amz-Trailer", None).trim().to_lowercase();
+    let algorithm = trailer.strip_prefix("x-amz-checksum-");
+    if algorithm.is_none() {
+        return Ok(None);
+    }
+
+    let checksum = TrailerChecksum::new(algorithm.unwrap());
+    if checksum.is_none() {
+        tracing::debug!("Unsupported trailing checksum: {}", trailer);
+        return Err(S3Error::NotImplemented(format!("The trailing checksum '{trailer}' is not supported.")));
+    }
+
+    Ok(checksum)
+}
+
+/// トレーラーで送られてくるチェックサムを、デコードしたデータから計算する
+enum TrailerChecksum {
+    Crc32(crc::Digest<'static, u32>),
+    Crc32c(crc::Digest<'static, u32>),
+    Crc64Nvme(crc::Digest<'static, u64>),
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl TrailerChecksum {
+    fn new(algorithm: &str) -> Option<Self> {
+        match algorithm {
+            "crc32" => Some(Self::Crc32(CRC32.digest())),
+            "crc32c" => Some(Self::Crc32c(CRC32C.digest())),
+            "crc64nvme" => Some(Self::Crc64Nvme(CRC64NVME.digest())),
+            "sha1" => Some(Self::Sha1(Sha1::new())),
+            "sha256" => Some(Self::Sha256(Sha256::new())),
+            _ => None,
+        }
+    }
+
+    /// トレーラーのヘッダー名
+    fn header_name(&self) -> &'static str {
+        match self {
+            Self::Crc32(_) => "x-amz-checksum-crc32",
+            Self::Crc32c(_) => "x-amz-checksum-crc32c",
+            Self::Crc64Nvme(_) => "x-amz-checksum-crc64nvme",
+            Self::Sha1(_) => "x-amz-checksum-sha1",
+            Self::Sha256(_) => "x-amz-checksum-sha256",
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Crc32(digest) | Self::Crc32c(digest) => digest.update(data),
+            Self::Crc64Nvme(digest) => digest.update(data),
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// トレーラーと同じ、Base64でエンコードした形式で返す
+    fn finalize(self) -> String {
+        match self {
+            Self::Crc32(digest) | Self::Crc32c(digest) => BASE64.encode(digest.finalize().to_be_bytes()),
+            Self::Crc64Nvme(digest) => BASE64.encode(digest.finalize().to_be_bytes()),
+            Self::Sha1(hasher) => BASE64.encode(hasher.finalize()),
+            Self::Sha256(hasher) => BASE64.encode(hasher.finalize()),
+        }
+    }
+}
+
+fn decode_chunked(
+    inner: BodyDataStream,
+    context: Option<SignatureContext>,
+    has_trailer: bool,
+    checksum: Option<TrailerChecksum>,
+    decoded_content_length: Option<u64>,
+    limit: u64,
+) -> ObjectStream {
+    let decoder = ChunkedPayloadDecoder {
+        inner,
+        buffer: BytesMut::new(),
+        previous_signature: context.as_ref().map(|c| c.seed_signature.clone()).unwrap_or_default(),
+        context,
+        has_trailer,
+        checksum,
+        decoded_content_length,
+        limit,
+        decoded_size: 0,
+        is_finished: false,
+    };
+
+    Box::pin(stream::try_unfold(decoder, |mut decoder| async move {
+        let chunk = decoder.next_chunk().await?;
+        Ok(chunk.map(|chunk| (chunk, decoder)))
+    }))
+}
+
+/// `Content-Encoding: aws-chunked` で送られてきたボディのデコーダー
+/// 署名付きの場合は各チャンクの署名を前のチャンクの署名と連鎖させて検証する
+struct ChunkedPayloadDecoder {
+    inner: BodyDataStream,
+    buffer: BytesMut,
+    context: Option<SignatureContext>,
+    previous_signature: String,
+    has_trailer: bool,
+    /// `X-Amz-Trailer`で宣言されたチェックサム
+    checksum: Option<TrailerChecksum>,
+    decoded_content_length: Option<u64>,
+    /// デコード後のサイズの上限
+    limit: u64,
+    decoded_size: u64,
+    is_finished: bool,
+}
+
+impl ChunkedPayloadDecoder {
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        if self.is_finished {
+            return Ok(None);
+        }
+
+        let header = self.read_line().await?;
+        let (size, extension) = header.split_once(';').unwrap_or((header.as_str(), ""));
+        let size = usize::from_str_radix(size.trim(), 16);
+        if size.is_err() {
+            return Err(invalid_encoding("Invalid chunk size"));
+        }
+
+        // チャンクのサイズはクライアントが指定するため、バッファに読み込む前に上限と比較する
+        let size = size.unwrap();
+        if size as u64 > self.limit - self.decoded_size {
+            tracing::debug!(
+                "Decoded payload size exceeds the limit. Limit: {}, Got: {}",
+                self.limit,
+                self.decoded_size + size as u64
+            );
+            return Err(S3Error::EntityTooLarge.into());
+        }
+
+        let chunk_signature = extension.trim().strip_prefix("chunk-signature=").map(|s| s.to_string());
+        let chunk = self.read_exact(size).await?;
+
+        if let Some(context) = &self.context {
+            if chunk_signature.is_none() {
+                return Err(invalid_encoding("Missing chunk signature"));
+            }
+
+            let chunk_signature = chunk_signature.unwrap();
+            let calculated_signature = context.sign_chunk(&self.previous_signature, &chunk);
+            if calculated_signature != chunk_signature {
+                tracing::debug!("Chunk signature mismatch. Expected: {}, Got: {}", calculated_signature, chunk_signature);
+                metrics::record_signature_failure(&S3Error::SignatureDoesNotMatch);
+                return Err(S3Error::SignatureDoesNotMatch.into());
+            }
+
+            self.previous_signature = calculated_signature;
+        }
+
+        if size == 0 {
+            if self.has_trailer {
+                self.read_trailer().await?;
+            } else {
+                self.expect_crlf().await?;
+            }
+
+            if let Some(decoded_content_length) = self.decoded_content_length &&
+                decoded_content_length != self.decoded_size
+            {
+                return Err(S3Error::IncompleteBody.into());
+            }
+
+            self.is_finished = true;
+            return Ok(None);
+        }
+
+        self.expect_crlf().await?;
+        self.decoded_size += size as u64;
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(&chunk);
+        }
+
+        Ok(Some(chunk))
+    }
+
+    async fn read_trailer(&mut self) -> Result<(), Error> {
+        let mut trailer = String::new();
+        let mut trailer_signature = None;
+        let mut trailing_checksums = Vec::new();
+
+        loop {
+            let line = self.read_line().await?;
+            if line.is_empty() {
+                break;
+            }
+
+            match line.split_once(':') {
+                Some((name, value)) if name.trim().eq_ignore_ascii_case("x-amz-trailer-signature") => {
+                    trailer_signature = Some(value.trim().to_string());
+                }
+                Some((name, value)) => {
+                    let name = name.trim().to_lowercase();
+                    if name.starts_with("x-amz-checksum-") {
+                        trailing_checksums.push((name.clone(), value.trim().to_string()));
+                    }
+
+                    trailer.push_str(&format!("{}:{}\n", name, value.trim()));
+                }
+                None => return Err(invalid_encoding("Invalid trailing header")),
+            }
+
+            if trailer.len() > MAX_LINE_LENGTH {
+                return Err(invalid_encoding("Trailing headers are too long"));
+            }
+        }
+
+        if let Some(context) = &self.context {
+            if trailer_signature.is_none() {
+                return Err(invalid_encoding("Missing trailer signature"));
+            }
+
+            let trailer_signature = trailer_signature.unwrap();
+            let calculated_signature = context.sign_trailer(&self.previous_signature, trailer.as_bytes());
+            if calculated_signature != trailer_signature {
+                tracing::debug!(
+                    "Trailer signature mismatch. Expected: {}, Got: {}",
+                    calculated_signature,
+                    trailer_signature
+                );
+                metrics::record_signature_failure(&S3Error::SignatureDoesNotMatch);
+                return Err(S3Error::SignatureDoesNotMatch.into());
+            }
+        }
+
+        self.verify_trailing_checksum(trailing_checksums)
+    }
+
+    /// トレーラーのチェックサムを、デコードしたデータから計算したものと比較する
+    /// 検証できないチェックサムを受け入れないよう、`X-Amz-Trailer`で宣言されていないチェックサムは拒否する
+    fn verify_trailing_checksum(&mut self, trailing_checksums: Vec<(String, String)>) -> Result<(), Error> {
+        let checksum = self.checksum.take();
+        let expected_name = checksum.as_ref().map(|checksum| checksum.header_name());
+        if let Some((name, _)) = trailing_checksums.iter().find(|(name, _)| Some(name.as_str()) != expected_name) {
+            return Err(invalid_encoding(&format!("Undeclared trailing checksum: {name}")));
+        }
+
+        if checksum.is_none() {
+            return Ok(());
+        }
+
+        let checksum = checksum.unwrap();
+        let name = checksum.header_name();
+        let expected_checksum = trailing_checksums.into_iter().next().map(|(_, value)| value);
+        if expected_checksum.is_none() {
+            return Err(invalid_encoding(&format!("Missing trailing checksum: {name}")));
+        }
+
+        let expected_checksum = expected_checksum.unwrap();
+        let calculated_checksum = checksum.finalize();
+        if calculated_checksum != expected_checksum {
+            tracing::debug!(
+                "Trailing checksum mismatch. Expected: {}, Got: {}",
+                calculated_checksum,
+                expected_checksum
+            );
+            return Err(S3Error::BadDigest.into());
+        }
+
+        Ok(())
+    }
+
+    /// バッファにデータを追加する。ボディの終端に達した場合はfalseを返す
+    async fn fill_buffer(&mut self) -> Result<bool, Error> {
+        match self.inner.next().await {
+            Some(chunk) => {
+                self.buffer.extend_from_slice(&chunk?);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String, Error> {
+        loop {
+            if let Some(position) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                let line = self.buffer.split_to(position);
+                self.buffer.advance(2);
+                return String::from_utf8(line.to_vec()).map_err(|_| invalid_encoding("Invalid chunk header"));
+            }
+
+            if self.buffer.len() > MAX_LINE_LENGTH {
+                return Err(invalid_encoding("Chunk header is too long"));
+            }
+
+            if !self.fill_buffer().await? {
+                return Err(S3Error::IncompleteBody.into());
+            }
+        }
+    }
+
+    async fn read_exact(&mut self, size: usize) -> Result<Bytes, Error> {
+        while self.buffer.len() < size {
+            if !self.fill_buffer().await? {
+                return Err(S3Error::IncompleteBody.into());
+            }
+        }
+
+        Ok(self.buffer.split_to(size).freeze())
+    }
+
+    async fn expect_crlf(&mut self) -> Result<(), Error> {
+        let crlf = self.read_exact(2).await?;
+        if crlf.as_ref() != b"\r\n" {
+            return Err(invalid_encoding("Chunk data is not terminated by CRLF"));
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_encoding(message: &str) -> Error {
+    S3Error::InvalidRequest(format!("Invalid aws-chunked encoding: {message}")).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // AWSのドキュメント (Signature Calculations for the Authorization Header: Transferring Payload in Multiple Chunks) の例
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    const AMZ_DATE: &str = "20130524T000000Z";
+    const CREDENTIALS_SCOPE: &str = "20130524/us-east-1/s3/aws4_request";
+    const SEED_SIGNATURE: &str = "4f232c4386841ef735655705268965c44a0e4690baa4adea153f7db9fa80a0a9";
+    const CHUNK_SIGNATURES: [&str; 3] = [
+        "ad80c730a21e5b8d04586a2213dd63b9a0e99e0e2307b0ade35a65485a288648",
+        "0055627c9e194cb4542bae2aa5492e3c1575bbb81b612b7d234b86a503ef5497",
+        "b6c6ea8a5354eaf15b3cb7646744f4275b71ea724fed81ceb9323e279d449df9",
+    ];
+
+    fn context(content_hash: &str) -> SignatureContext {
+        SignatureContext::new_for_test(content_hash, SEED_SIGNATURE, AMZ_DATE, CREDENTIALS_SCOPE, SECRET_KEY)
+    }
+
+    fn signed_chunk(size: usize, data: &[u8], signature: &str) -> Vec<u8> {
+        let mut chunk = format!("{size:x};chunk-signature={signature}\r\n").into_bytes();
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(b"\r\n");
+        chunk
+    }
+
+    /// AWSの例と同じ、64KiBと1KiBのチャンクからなるボディ
+    fn example_payload() -> Vec<u8> {
+        let mut payload = signed_chunk(65536, &[b'a'; 65536], CHUNK_SIGNATURES[0]);
+        payload.extend(signed_chunk(1024, &[b'a'; 1024], CHUNK_SIGNATURES[1]));
+        payload.extend(format!("0;chunk-signature={}\r\n\r\n", CHUNK_SIGNATURES[2]).into_bytes());
+        payload
+    }
+
+    async fn decode(payload: &[u8], context: Option<&SignatureContext>, decoded_content_length: Option<u64>) -> Result<Vec<u8>, Error> {
+        decode_with_trailer(payload, context, decoded_content_length, None).await
+    }
+
+    /// チャンクの境界と関係なく分割して送られてきた場合も同じ結果になることを確認するため、7バイトごとに分割する
+    async fn decode_with_trailer(
+        payload: &[u8],
+        context: Option<&SignatureContext>,
+        decoded_content_length: Option<u64>,
+        trailer: Option<&str>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut headers = HeaderMap::new();
+        if let Some(decoded_content_length) = decoded_content_length {
+            headers.insert("X-Amz-Decoded-Content-Length", decoded_content_length.into());
+        }
+        if let Some(trailer) = trailer {
+            headers.insert("X-Amz-Trailer", trailer.parse().unwrap());
+        }
+
+        let frames = payload
+            .chunks(7)
+            .map(|frame| Ok::<_, std::io::Error>(Bytes::copy_from_slice(frame)))
+            .collect::<Vec<_>>();
+        let mut stream = verify_payload(Body::from_stream(stream::iter(frames)), context, &headers, u64::MAX)?;
+
+        let mut decoded = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            decoded.extend_from_slice(&chunk?);
+        }
+
+        Ok(decoded)
+    }
+
+    fn error_code(result: Result<Vec<u8>, Error>) -> &'static str {
+        result.unwrap_err().downcast::<S3Error>().unwrap().code()
+    }
+
+    #[tokio::test]
+    async fn decodes_unsigned_chunks() {
+        let context = context(STREAMING_UNSIGNED_PAYLOAD_TRAILER);
+        let payload = b"5\r\nhello\r\n6\r\n world\r\n0\r\nx-amz-checksum-crc32:DUoRhQ==\r\n\r\n";
+
+        let decoded = decode_with_trailer(payload, Some(&context), Some(11), Some("x-amz-checksum-crc32")).await;
+        assert_eq!(decoded.unwrap(), b"hello world");
+    }
+
+    #[tokio::test]
+    async fn verifies_trailing_checksum() {
+        let context = context(STREAMING_UNSIGNED_PAYLOAD_TRAILER);
+        let cases = [
+            ("x-amz-checksum-crc32", "DUoRhQ=="),
+            ("x-amz-checksum-crc32c", "yZRlqg=="),
+            ("x-amz-checksum-crc64nvme", "jSnVw/bqjr4="),
+            ("x-amz-checksum-sha1", "Kq5sNclPz7QV2+lfQIuc6R7oRu0="),
+            ("x-amz-checksum-sha256", "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="),
+        ];
+        for (name, checksum) in cases {
+            let payload = format!("5\r\nhello\r\n6\r\n world\r\n0\r\n{name}:{checksum}\r\n\r\n");
+            let decoded = decode_with_trailer(payload.as_bytes(), Some(&context), None, Some(name)).await;
+            assert_eq!(decoded.unwrap(), b"hello world", "{name}");
+
+            // データと一致しないチェックサム
+            let payload = payload.replace(" world", " WORLD");
+            let decoded = decode_with_trailer(payload.as_bytes(), Some(&context), None, Some(name)).await;
+            assert_eq!(error_code(decoded), "BadDigest", "{name}");
+        }
+
+        // 宣言されたチェックサムがない、または宣言されていないチェックサムが送られてきた
+        let payload = b"5\r\nhello\r\n0\r\n\r\n";
+        let decoded = decode_with_trailer(payload, Some(&context), None, Some("x-amz-checksum-crc32")).await;
+        assert_eq!(error_code(decoded), "InvalidRequest");
+
+        let payload = b"5\r\nhello\r\n0\r\nx-amz-checksum-crc32:NhCmhg==\r\n\r\n";
+        let decoded = decode_with_trailer(payload, Some(&context), None, Some("x-amz-checksum-sha256")).await;
+        assert_eq!(error_code(decoded), "InvalidRequest");
+        assert_eq!(error_code(decode(payload, Some(&context), None).await), "InvalidRequest");
+
+        // 対応していないアルゴリズム
+        let decoded = decode_with_trailer(payload, Some(&context), None, Some("x-amz-checksum-md5")).await;
+        assert_eq!(error_code(decoded), "NotImplemented");
+    }
+
+    #[tokio::test]
+    async fn verifies_chained_chunk_signatures() {
+        let context = context(STREAMING_SIGNED_PAYLOAD);
+
+        let decoded = decode(&example_payload(), Some(&context), Some(66560)).await.unwrap();
+        assert_eq!(decoded.len(), 66560);
+        assert!(decoded.iter().all(|&byte| byte == b'a'));
+    }
+
+    #[tokio::test]
+    async fn rejects_chunk_signature_out_of_chain() {
+        let context = context(STREAMING_SIGNED_PAYLOAD);
+
+        // 2番目のチャンクの署名は1番目のチャンクの署名から計算されるため、先頭に置くと一致しない
+        let mut payload = signed_chunk(1024, &[b'a'; 1024], CHUNK_SIGNATURES[1]);
+        payload.extend(format!("0;chunk-signature={}\r\n\r\n", CHUNK_SIGNATURES[2]).into_bytes());
+        assert_eq!(error_code(decode(&payload, Some(&context), None).await), "SignatureDoesNotMatch");
+
+        // 最後のチャンクの署名も検証する
+        let mut payload = example_payload();
+        let last_signature = payload.len() - 68;
+        payload[last_signature..last_signature + 4].copy_from_slice(b"0000");
+        assert_eq!(error_code(decode(&payload, Some(&context), None).await), "SignatureDoesNotMatch");
+
+        let payload = b"5\r\nhello\r\n0\r\n\r\n";
+        assert_eq!(error_code(decode(payload, Some(&context), None).await), "InvalidRequest");
+    }
+
+    #[tokio::test]
+    async fn verifies_trailer_signature() {
+        const TRAILER: &str = "x-amz-checksum-crc32";
+        let context = context(STREAMING_SIGNED_PAYLOAD_TRAILER);
+        let first_signature = context.sign_chunk(SEED_SIGNATURE, b"hello");
+        let last_signature = context.sign_chunk(&first_signature, b"");
+        let trailer_signature = context.sign_trailer(&last_signature, b"x-amz-checksum-crc32:NhCmhg==\n");
+
+        let mut payload = signed_chunk(5, b"hello", &first_signature);
+        payload.extend(format!("0;chunk-signature={last_signature}\r\nx-amz-checksum-crc32:NhCmhg==\r\n").into_bytes());
+
+        let mut signed = payload.clone();
+        signed.extend(format!("x-amz-trailer-signature:{trailer_signature}\r\n\r\n").into_bytes());
+        assert_eq!(
+            decode_with_trailer(&signed, Some(&context), Some(5), Some(TRAILER)).await.unwrap(),
+            b"hello"
+        );
+
+        // トレーラーの内容が署名と一致しない
+        let tampered = String::from_utf8(signed).unwrap().replace("NhCmhg==", "AAAAAA==");
+        let decoded = decode_with_trailer(tampered.as_bytes(), Some(&context), None, Some(TRAILER)).await;
+        assert_eq!(error_code(decoded), "SignatureDoesNotMatch");
+
+        // トレーラーの署名がない
+        payload.extend_from_slice(b"\r\n");
+        let decoded = decode_with_trailer(&payload, Some(&context), None, Some(TRAILER)).await;
+        assert_eq!(error_code(decoded), "InvalidRequest");
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_payload() {
+        let context = context(STREAMING_SIGNED_PAYLOAD);
+        let payload = example_payload();
+
+        // 1番目のチャンクの途中、2番目のチャンクヘッダーの途中、最後のチャンクヘッダーの途中で途切れた場合
+        for length in [1000, 65536 + 100, 65536 + 1024 + 200] {
+            assert_eq!(error_code(decode(&payload[..length], Some(&context), None).await), "IncompleteBody");
+        }
+
+        // デコード後のサイズが`x-amz-decoded-content-length`と一致しない
+        assert_eq!(error_code(decode(&payload, Some(&context), Some(66561)).await), "IncompleteBody");
+    }
+
+    async fn collect(body: &[u8], context: Option<&SignatureContext>, limit: u64) -> Vec<Result<Bytes, Error>> {
+        let stream = verify_payload(Body::from(body.to_vec()), context, &HeaderMap::new(), limit).unwrap();
+        stream.collect::<Vec<_>>().await
+    }
+
+    #[tokio::test]
+    async fn limits_decoded_payload_size() {
+        let chunked = context(STREAMING_UNSIGNED_PAYLOAD_TRAILER);
+        let payload = b"5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+
+        // チャンクのヘッダーはサイズに含めない
+        let decoded = collect(payload, Some(&chunked), 11).await;
+        assert!(decoded.iter().all(|chunk| chunk.is_ok()));
+
+        // 2番目のチャンクを読み込む前に、それまでのサイズとの合計で上限を超えることを検出する
+        let decoded = collect(payload, Some(&chunked), 10).await;
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].as_ref().unwrap_err().downcast_ref::<S3Error>(), Some(&S3Error::EntityTooLarge));
+
+        // aws-chunkedではないボディにも上限を適用する
+        for context in [None, Some(context(UNSIGNED_PAYLOAD))] {
+            assert!(collect(b"hello world", context.as_ref(), 11).await.iter().all(|chunk| chunk.is_ok()));
+
+            let decoded = collect(b"hello world", context.as_ref(), 10).await;
+            assert_eq!(
+                decoded.last().unwrap().as_ref().unwrap_err().downcast_ref::<S3Error>(),
+                Some(&S3Error::EntityTooLarge)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_chunk_before_reading() {
+        let context = context(STREAMING_UNSIGNED_PAYLOAD_TRAILER);
+
+        // チャンクのデータを待たずに拒否されることを確認するため、ヘッダーの後は何も送らないボディにする
+        let header = stream::iter([Ok::<_, std::io::Error>(Bytes::from_static(b"ffffffff\r\nhello"))]);
+        let body = Body::from_stream(header.chain(stream::pending()));
+        let mut stream = verify_payload(body, Some(&context), &HeaderMap::new(), 1024).unwrap();
+
+        let result = stream.next().await.unwrap();
+        assert_eq!(result.unwrap_err().downcast_ref::<S3Error>(), Some(&S3Error::EntityTooLarge));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_chunks() {
+        let context = context(STREAMING_UNSIGNED_PAYLOAD_TRAILER);
+
+        let cases: [&[u8]; 4] = [
+            // チャンクサイズが16進数ではない
+            b"zz\r\nhello\r\n0\r\n\r\n",
+            // チャンクの後にCRLFがない
+            b"5\r\nhelloXX0\r\n\r\n",
+            // トレーラーの形式が不正
+            b"5\r\nhello\r\n0\r\ninvalid trailer\r\n\r\n",
+            // チャンクヘッダーがCRLFで終わらないまま上限を超える
+            &[b'1'; MAX_LINE_LENGTH + 16],
+        ];
+        for payload in cases {
+            assert_eq!(error_code(decode(payload, Some(&context), None).await), "InvalidRequest");
+        }
+    }
+}
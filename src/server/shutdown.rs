@@ -0,0 +1,52 @@
+use axum_server::Handle;
+use std::{net::SocketAddr, time::Duration};
+use tokio::signal;
+
+/// 終了のシグナルを受信したら新しい接続の受け付けを止め、処理中のリクエストが完了するまで待機する
+/// `timeout`を過ぎても完了しないリクエストは切断する。待機中に再度シグナルを受信した場合はすぐに終了する
+pub fn spawn_watcher(handle: Handle<SocketAddr>, timeout: Duration) {
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        tracing::info!(
+            "Shutting down, waiting up to {}s for {} connections to finish",
+            timeout.as_secs(),
+            handle.connection_count()
+        );
+        handle.graceful_shutdown(Some(timeout));
+
+        wait_for_signal().await;
+        tracing::warn!("Received the signal again, closing all connections");
+        handle.shutdown();
+    });
+}
+
+/// SIGINTまたはSIGTERMを受信するまで待機する
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        if let Err(err) = signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
@@ -0,0 +1,81 @@
+use crate::config::CFGServerTls;
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::{
+    ServerConfig,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+use std::{
+    fs,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::time;
+
+/// 証明書と秘密鍵を読み込み、TLSの設定を生成する
+pub fn load_config(tls: &CFGServerTls) -> anyhow::Result<RustlsConfig> {
+    Ok(RustlsConfig::from_config(Arc::new(build_server_config(tls)?)))
+}
+
+/// 証明書ファイルの更新を定期的に確認し、変更された場合は再起動せずに読み込み直す
+pub fn spawn_reloader(config: RustlsConfig, tls: CFGServerTls) {
+    if tls.reload_interval_seconds == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut last_modified = get_modified(&tls);
+        let mut interval = time::interval(Duration::from_secs(tls.reload_interval_seconds));
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let modified = get_modified(&tls);
+            if modified.is_none() || modified == last_modified {
+                continue;
+            }
+
+            // 証明書と秘密鍵の書き換えの途中で読み込んだ場合は失敗するため、更新日時を記録せず次回に再試行する
+            let server_config = build_server_config(&tls);
+            if let Err(err) = server_config {
+                tracing::warn!("Failed to reload the TLS certificate: {:#}", err);
+                continue;
+            }
+
+            config.reload_from_config(Arc::new(server_config.unwrap()));
+            last_modified = modified;
+            tracing::info!("Reloaded the TLS certificate from {}", tls.cert_path);
+        }
+    });
+}
+
+fn build_server_config(tls: &CFGServerTls) -> anyhow::Result<ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(&tls.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read the certificate from {}", tls.cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(&tls.key_path).with_context(|| format!("Failed to read the private key from {}", tls.key_path))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("The certificate and the private key do not match")?;
+
+    config.alpn_protocols = if tls.http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+
+    Ok(config)
+}
+
+/// 証明書と秘密鍵のうち、新しい方の更新日時を返す
+/// シンボリックリンクはリンク先の更新日時を参照する
+fn get_modified(tls: &CFGServerTls) -> Option<SystemTime> {
+    let cert_modified = fs::metadata(&tls.cert_path).and_then(|metadata| metadata.modified()).ok()?;
+    let key_modified = fs::metadata(&tls.key_path).and_then(|metadata| metadata.modified()).ok()?;
+    Some(cert_modified.max(key_modified))
+}
@@ -1,6 +1,16 @@
-use axum::http::{HeaderMap, HeaderValue};
+use crate::{
+    server::{AppResult, error::S3Error},
+    storage::ObjectHeaders,
+};
+use axum::{
+    http::{HeaderMap, HeaderName, HeaderValue},
+    response::{IntoResponse, Response},
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Serialize;
+
+pub const S3_XML_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
 
 pub fn get_header(header: &HeaderMap<HeaderValue>, header_name: &str, fallback: Option<String>) -> String {
     header
@@ -51,6 +61,77 @@ pub fn parse_content_disposition(content_disposition: &str) -> ContentDispositio
     result
 }
 
+/// ユーザー定義メタデータの合計サイズの上限 (名前と値のバイト数の合計)
+const MAX_USER_METADATA_SIZE: usize = 2 * 1024;
+const USER_METADATA_PREFIX: &str = "x-amz-meta-";
+
+/// リクエストヘッダーから、オブジェクトと共に保存するヘッダーを取り出す
+pub fn parse_object_headers(header: &HeaderMap<HeaderValue>) -> Result<ObjectHeaders, S3Error> {
+    let get = |name: &str| Some(get_header(header, name, None)).filter(|value| !value.is_empty());
+    let mut result = ObjectHeaders {
+        cache_control: get("Cache-Control"),
+        content_encoding: get("Content-Encoding"),
+        content_language: get("Content-Language"),
+        expires: get("Expires"),
+        ..Default::default()
+    };
+
+    // aws-chunkedはペイロードの転送方法なので保存しない
+    if let Some(content_encoding) = &result.content_encoding {
+        let encodings = content_encoding
+            .split(',')
+            .map(|encoding| encoding.trim())
+            .filter(|encoding| !encoding.eq_ignore_ascii_case("aws-chunked"))
+            .collect::<Vec<_>>();
+        result.content_encoding = Some(encodings.join(", ")).filter(|value| !value.is_empty());
+    }
+
+    let mut user_metadata_size = 0;
+    for (name, value) in header {
+        let Some(key) = name.as_str().strip_prefix(USER_METADATA_PREFIX) else {
+            continue;
+        };
+
+        let Ok(value) = value.to_str() else {
+            return Err(S3Error::InvalidArgument(format!("Invalid value of the metadata header: {name}")));
+        };
+
+        user_metadata_size += key.len() + value.len();
+        result.user_metadata.insert(key.to_string(), value.to_string());
+    }
+
+    if user_metadata_size > MAX_USER_METADATA_SIZE {
+        return Err(S3Error::MetadataTooLarge);
+    }
+
+    Ok(result)
+}
+
+/// 保存されたヘッダーをレスポンスヘッダーに設定する
+pub fn insert_object_headers(header: &mut HeaderMap<HeaderValue>, object_headers: &ObjectHeaders) {
+    let mut insert = |name: String, value: &str| {
+        if let (Ok(name), Ok(value)) = (name.parse::<HeaderName>(), value.parse::<HeaderValue>()) {
+            header.insert(name, value);
+        }
+    };
+
+    let standard_headers = [
+        ("Cache-Control", &object_headers.cache_control),
+        ("Content-Encoding", &object_headers.content_encoding),
+        ("Content-Language", &object_headers.content_language),
+        ("Expires", &object_headers.expires),
+    ];
+    for (name, value) in standard_headers {
+        if let Some(value) = value {
+            insert(name.to_string(), value);
+        }
+    }
+
+    for (key, value) in &object_headers.user_metadata {
+        insert(format!("{USER_METADATA_PREFIX}{key}"), value);
+    }
+}
+
 pub fn build_content_disposition_filename(filename: Option<String>, encoded_filename: Option<String>) -> Vec<String> {
     let mut result = vec![];
 
@@ -70,3 +151,46 @@ pub fn build_content_disposition_filename(filename: Option<String>, encoded_file
 
     result
 }
+
+pub fn xml_response<T: Serialize>(value: &T) -> AppResult<Response> {
+    let xml_response = serde_xml_rs::to_string(value);
+    if let Err(e) = xml_response {
+        return Err(e.into());
+    }
+
+    let mut response = xml_response.unwrap().into_response();
+    response.headers_mut().insert("Content-Type", "application/xml".parse().unwrap());
+
+    Ok(response)
+}
+
+/// バケット名とキーからオブジェクトのパスを生成する
+/// オブジェクトのパスはリクエストURIのパスと同じ形式で保存されているため、SDKと同じ方法でキーをエンコードする
+pub fn build_object_path(bucket: &str, key: &str) -> String {
+    format!("/{bucket}/{}", encode_object_key(key))
+}
+
+/// キーをオブジェクトのパスと同じ形式にエンコードする
+pub fn encode_object_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| urlencoding::encode(segment).to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// オブジェクトのパスと同じ形式でエンコードされたキーをデコードする
+pub fn decode_object_key(key: &str) -> String {
+    urlencoding::decode(key).map_or(key.to_string(), |decoded| decoded.into_owned())
+}
+
+/// オブジェクトのパス (`/bucket/key`) をデコードし、アクセスキーの接頭辞と比較できる`bucket/key`の形式にする
+pub fn decode_object_path(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    urlencoding::decode(path).map_or(path.to_string(), |decoded| decoded.into_owned())
+}
+
+/// オブジェクトのETagをダブルクォートで囲んだ形式で返す
+/// ETagが記録されていないオブジェクトは、従来通りinternal_filenameをETagとして使用する
+pub fn format_e_tag(object: &entity::object::Model) -> String {
+    format!("\"{}\"", object.e_tag.as_deref().unwrap_or(&object.internal_filename))
+}
@@ -1,26 +1,28 @@
-use crate::config;
+use crate::{config, server::error::S3Error};
 use anyhow::Error;
-use axum::body::BodyDataStream;
+use bytes::Bytes;
 use chrono::{DateTime, TimeDelta, Utc};
-use sea_orm::ActiveValue::Set;
+use futures::Stream;
+use md5::{Digest, Md5};
+use sea_orm::{ActiveValue::Set, JsonValue};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
     future::Future,
-    path::Path,
+    path::{Path, PathBuf},
     pin::Pin,
     process,
-    sync::{
-        Arc, LazyLock, Mutex,
-        atomic::{AtomicBool, Ordering},
-    },
+    sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
 use tokio::{fs::File, time};
 use uuid::Uuid;
 
+mod blob;
 mod file;
 mod metadata;
+mod multipart;
 
 #[derive(Debug)]
 pub struct ReadObjectData {
@@ -28,154 +30,669 @@ pub struct ReadObjectData {
     pub file: Option<File>,
 }
 
-#[derive(Debug)]
+/// 書き込むオブジェクトのデータストリーム
+pub type ObjectStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>;
+
 pub struct WriteObjectData {
-    pub binary: BodyDataStream,
+    pub binary: ObjectStream,
     pub path: String,
     pub filename: Option<String>,
     pub encoded_filename: Option<String>,
-    pub content_size: i64,
     pub mime_type: String,
+    pub headers: ObjectHeaders,
 }
 
-// Multipart upload state management
-pub type MultipartUploadState = Arc<Mutex<HashMap<String, MultipartUploadItem>>>;
-pub static MULTIPART_UPLOAD_STATE: LazyLock<MultipartUploadState> = LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
+/// アップロード時に指定され、GET/HEADでそのまま返すヘッダー
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectHeaders {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+    /// `x-amz-meta-`を除いた小文字のヘッダー名をキーとするユーザー定義メタデータ
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub user_metadata: BTreeMap<String, String>,
+}
 
-#[derive(Clone)]
-pub struct MultipartUploadItem {
-    pub path: String,
-    pub filename: Option<String>,
-    pub encoded_filename: Option<String>,
-    pub mime_type: String,
-    pub last_upload_at: DateTime<Utc>,
+impl ObjectHeaders {
+    pub fn from_json(value: Option<&JsonValue>) -> Self {
+        let Some(value) = value else {
+            return Self::default();
+        };
+
+        let headers = serde_json::from_value::<Self>(value.clone());
+        if let Err(e) = headers {
+            tracing::warn!("Failed to parse stored object headers: {}", e);
+            return Self::default();
+        }
+
+        headers.unwrap()
+    }
+
+    /// 何も指定されていない場合はNULLとして保存する
+    fn to_json(&self) -> Option<JsonValue> {
+        if *self == Self::default() {
+            return None;
+        }
+
+        serde_json::to_value(self).ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct ListObjectsOptions {
+    pub prefix: String,
+    pub delimiter: Option<String>,
+    pub start_after: Option<String>,
+    pub max_keys: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ListObjectsResult {
+    pub objects: Vec<entity::object::Model>,
+    pub common_prefixes: Vec<String>,
+    pub is_truncated: bool,
+    pub next_marker: Option<String>,
 }
 
+const LIST_OBJECTS_BATCH_SIZE: u64 = 1000;
+
+// Multipart upload state management
+pub type MultipartUploadItem = entity::multipart_upload::Model;
+
 pub async fn initialize() {
-    let base_path = Path::new(&config::CONFIG.bucket.path);
-    if !base_path.exists() {
-        let result = fs::create_dir_all(base_path);
-        if result.is_err() {
-            tracing::error!("Failed to create bucket path: {}", result.unwrap_err());
-            process::exit(1);
-        } else {
-            tracing::info!("Bucket dir created successfully: {}", base_path.display());
+    for storage_path in config::CONFIG.storage_paths() {
+        let base_path = Path::new(&storage_path);
+        if !base_path.exists() {
+            if let Err(e) = fs::create_dir_all(base_path) {
+                tracing::error!("Failed to create bucket path: {}", e);
+                process::exit(1);
+            } else {
+                tracing::info!("Bucket dir created successfully: {}", base_path.display());
+            }
+        }
+
+        if let Err(e) = file::clear_temporary(base_path).await {
+            tracing::error!("Failed to clear temporary files: {}", e);
         }
     }
 
-    let temp_path = base_path.join(".multipart");
-    if temp_path.exists() &&
-        let Err(e) = fs::remove_dir_all(&temp_path)
-    {
-        tracing::error!("Failed to remove expired multipart uploads: {}", e);
+    if config::CONFIG.buckets.is_empty() {
+        tracing::info!("No buckets are declared in config.toml. Requests to any bucket name are accepted and stored in `bucket.path`.");
+    }
+
+    if let Err(e) = resume_multipart_uploads().await {
+        tracing::error!("Failed to resume multipart uploads: {}", e);
     }
 }
 
+async fn resume_multipart_uploads() -> Result<(), Error> {
+    // 期限切れのアップロードを削除
+    remove_expired_multipart_uploads().await?;
+
+    // DBに存在しないアップロードのディレクトリを削除
+    let upload_ids = multipart::list_uploads()
+        .await?
+        .into_iter()
+        .map(|upload| upload.upload_id)
+        .collect::<HashSet<String>>();
+
+    for storage_path in config::CONFIG.storage_paths() {
+        let root = Path::new(&storage_path);
+        let temp_path = root.join(".multipart");
+        if !temp_path.exists() {
+            continue;
+        }
+
+        for entry in fs::read_dir(&temp_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if upload_ids.contains(&name) {
+                continue;
+            }
+
+            tracing::debug!("Removing orphaned multipart upload: {}", name);
+            if let Err(e) = file::delete_object(root, name.clone(), true).await {
+                tracing::error!("Failed to remove orphaned multipart upload {}: {}", name, e);
+            }
+        }
+    }
+
+    tokio::spawn(internal_cleanup());
+    Ok(())
+}
+
+/// バケットのディレクトリの作成日時を返す
+pub fn bucket_created_at(bucket: &config::BucketConfig) -> Option<DateTime<Utc>> {
+    let metadata = fs::metadata(&bucket.path).ok()?;
+    metadata.created().or(metadata.modified()).ok().map(DateTime::<Utc>::from)
+}
+
 pub async fn get_object(path: String, with_file: bool) -> Result<ReadObjectData, Error> {
     let metadata = metadata::get_metadata_by_path(&path).await;
     if metadata.is_none() {
-        return Err(anyhow::anyhow!("Object metadata not found for path: {}", path));
+        return Err(S3Error::NoSuchKey.into());
     }
 
     let object_data = metadata.unwrap();
     let internal_filename = object_data.internal_filename.clone();
+    let root = object_root(&object_data)?;
 
     Ok(ReadObjectData {
         metadata: object_data,
         file: if with_file {
-            Some(file::read_object(internal_filename).await?)
+            Some(file::read_object(&root, internal_filename).await?)
         } else {
             None
         },
     })
 }
 
-pub async fn put_object(data: WriteObjectData) -> Result<(), Error> {
-    let internal_path = blake3::hash(data.path.as_bytes()).to_hex().to_string();
+pub async fn list_objects(bucket: &str, options: ListObjectsOptions) -> Result<ListObjectsResult, Error> {
+    collect_objects(bucket, options, LIST_OBJECTS_BATCH_SIZE, |prefix, after, limit| async move {
+        metadata::list_metadata(&prefix, &after, limit).await
+    })
+    .await
+}
+
+/// `fetch`で読み込んだオブジェクトを、区切り文字で共通プレフィックスにまとめながら`max_keys`件まで集める
+/// `fetch`は、パスが`prefix`で始まり`after`より大きいオブジェクトを、パスのバイト順に`limit`件まで返す
+async fn collect_objects<F, Fut>(bucket: &str, options: ListObjectsOptions, batch_size: u64, mut fetch: F) -> Result<ListObjectsResult, Error>
+where
+    F: FnMut(String, String, u64) -> Fut,
+    Fut: Future<Output = Result<Vec<entity::object::Model>, Error>>,
+{
+    let mut result = ListObjectsResult::default();
+    if options.max_keys == 0 {
+        return Ok(result);
+    }
+
+    let base_path = format!("/{bucket}/");
+    let path_prefix = format!("{base_path}{}", options.prefix);
+    let delimiter = options.delimiter.filter(|d| !d.is_empty());
+
+    // 再開位置が共通プレフィックスだった場合、そのプレフィックス配下のキーを再度返さないようにする
+    let mut last_common_prefix = options.start_after.clone();
+    let mut cursor = options.start_after.map(|s| format!("{base_path}{s}")).unwrap_or_default();
+    let mut count = 0;
+
+    'outer: loop {
+        let items = fetch(path_prefix.clone(), cursor.clone(), batch_size).await?;
+        let is_exhausted = (items.len() as u64) < batch_size;
+        let mut is_skipped = false;
+
+        for item in items {
+            cursor = item.path.clone();
+            let key = item.path[base_path.len()..].to_string();
+            if !key.starts_with(&options.prefix) {
+                // SQLiteのLIKEは大文字小文字を区別しないため、ここで改めて確認する
+                continue;
+            }
+
+            let common_prefix = delimiter.as_ref().and_then(|delimiter| {
+                key[options.prefix.len()..]
+                    .find(delimiter.as_str())
+                    .map(|idx| key[..options.prefix.len() + idx + delimiter.len()].to_string())
+            });
+
+            if let Some(common_prefix) = common_prefix {
+                if last_common_prefix.as_ref() == Some(&common_prefix) {
+                    continue;
+                }
+
+                if count >= options.max_keys {
+                    result.is_truncated = true;
+                    break 'outer;
+                }
+
+                // 共通プレフィックス配下の残りのキーは読み飛ばす
+                // パスはエンコードされたASCII文字列のため、char::MAXを付けると配下のどのパスよりも後ろになる
+                cursor = format!("{base_path}{common_prefix}{}", char::MAX);
+                result.next_marker = Some(common_prefix.clone());
+                result.common_prefixes.push(common_prefix.clone());
+                last_common_prefix = Some(common_prefix);
+                count += 1;
+                is_skipped = true;
+                break;
+            }
+
+            if count >= options.max_keys {
+                result.is_truncated = true;
+                break 'outer;
+            }
+
+            result.next_marker = Some(key);
+            result.objects.push(item);
+            count += 1;
+        }
+
+        if is_exhausted && !is_skipped {
+            break;
+        }
+    }
+
+    if !result.is_truncated {
+        result.next_marker = None;
+    }
+
+    Ok(result)
+}
+
+pub async fn put_object(data: WriteObjectData) -> Result<entity::object::Model, Error> {
+    // ペイロードの検証はストリームの読み込み中に行われるため、一時ファイルへの書き込みと配置が完了してからメタデータを作成する
+    let root = storage_root(&data.path)?;
+    let (internal_path, content_size, e_tag) = if config::CONFIG.bucket.dedupe {
+        let temporary = file::write_temporary(&root, data.binary).await?;
+        let (content_size, e_tag) = (temporary.size, temporary.md5.clone());
+        (store_blob(temporary).await?, content_size, e_tag)
+    } else {
+        let internal_path = new_internal_filename(&data.path);
+        let written = file::write_object(&root, internal_path.clone(), data.binary, false).await?;
+        (internal_path, written.size, written.md5)
+    };
+
+    let now = Utc::now().fixed_offset();
     let metadata = entity::object::ActiveModel {
         internal_filename: Set(internal_path.clone()),
         path: Set(data.path),
         filename: Set(data.filename),
         encoded_filename: Set(data.encoded_filename),
-        content_size: Set(data.content_size),
+        content_size: Set(content_size as i64),
         mime_type: Set(data.mime_type),
+        updated_at: Set(Some(now)),
+        created_at: Set(Some(now)),
+        e_tag: Set(Some(e_tag)),
+        headers: Set(data.headers.to_json()),
         ..Default::default()
     };
 
-    metadata::create_metadata(metadata).await?;
-    file::write_object(internal_path.clone(), data.binary, false).await?;
+    put_metadata(metadata, &root, &internal_path, None).await
+}
 
-    Ok(())
+/// コピー時に置き換えるメタデータ
+/// `x-amz-metadata-directive: REPLACE`の場合のみ使用する
+#[derive(Debug)]
+pub struct ReplaceMetadata {
+    pub filename: Option<String>,
+    pub encoded_filename: Option<String>,
+    pub mime_type: String,
+    pub headers: ObjectHeaders,
 }
 
-pub fn create_multipart_upload(path: String, filename: Option<String>, encoded_filename: Option<String>, mime_type: String) -> String {
+pub async fn copy_object(source_path: String, destination_path: String, replace: Option<ReplaceMetadata>) -> Result<entity::object::Model, Error> {
+    let source = metadata::get_metadata_by_path(&source_path).await;
+    if source.is_none() {
+        return Err(S3Error::NoSuchKey.into());
+    }
+    let source = source.unwrap();
+
+    if source_path == destination_path {
+        // 自身へのコピーはメタデータの置き換えとしてのみ許可する
+        if replace.is_none() {
+            return Err(S3Error::InvalidRequest(
+                "This copy request is illegal because it is trying to copy an object to itself without changing the object's metadata.".to_string(),
+            )
+            .into());
+        }
+
+        let replace = replace.unwrap();
+        let mut model: entity::object::ActiveModel = source.into();
+        model.filename = Set(replace.filename);
+        model.encoded_filename = Set(replace.encoded_filename);
+        model.mime_type = Set(replace.mime_type);
+        model.headers = Set(replace.headers.to_json());
+        model.updated_at = Set(Some(Utc::now().fixed_offset()));
+        return metadata::update_metadata(model).await;
+    }
+
+    // blobを共有する場合は内容を読み込まないため、コピー元のETagを引き継ぐ
+    let source_root = object_root(&source)?;
+    let root = storage_root(&destination_path)?;
+    let (internal_path, content_size, e_tag) = if config::CONFIG.bucket.dedupe {
+        (
+            duplicate_blob(&source, &source_root, &root).await?,
+            source.content_size as u64,
+            source.e_tag.clone(),
+        )
+    } else {
+        let internal_path = new_internal_filename(&destination_path);
+        let written = file::copy_object(&source_root, source.internal_filename.clone(), &root, internal_path.clone(), None, false).await?;
+        (internal_path, written.size, Some(written.md5))
+    };
+
+    let (filename, encoded_filename, mime_type, headers) = match replace {
+        Some(replace) => (replace.filename, replace.encoded_filename, replace.mime_type, replace.headers.to_json()),
+        None => (source.filename, source.encoded_filename, source.mime_type, source.headers),
+    };
+
+    let now = Utc::now().fixed_offset();
+    let metadata = entity::object::ActiveModel {
+        internal_filename: Set(internal_path.clone()),
+        path: Set(destination_path),
+        filename: Set(filename),
+        encoded_filename: Set(encoded_filename),
+        content_size: Set(content_size as i64),
+        mime_type: Set(mime_type),
+        updated_at: Set(Some(now)),
+        created_at: Set(Some(now)),
+        e_tag: Set(e_tag),
+        headers: Set(headers),
+        ..Default::default()
+    };
+
+    let model = put_metadata(metadata, &root, &internal_path, None).await?;
+    tracing::debug!("Object copied from {} to {}", source_path, model.path);
+    Ok(model)
+}
+
+pub async fn get_multipart_upload(upload_id: &str) -> Option<MultipartUploadItem> {
+    multipart::get_upload(upload_id).await
+}
+
+#[derive(Debug, Default)]
+pub struct ListPartsResult {
+    pub parts: Vec<entity::multipart_upload_part::Model>,
+    pub is_truncated: bool,
+}
+
+/// アップロード済みのパートを、パート番号が`part_number_marker`より大きいものから返す
+pub async fn list_parts(upload_id: &str, path: &str, part_number_marker: i32, max_parts: usize) -> Result<ListPartsResult, Error> {
+    let upload = multipart::get_upload(upload_id).await;
+    if upload.is_none_or(|upload| upload.path != path) {
+        return Err(S3Error::NoSuchUpload.into());
+    }
+
+    let mut parts = multipart::list_parts(upload_id).await?;
+    parts.retain(|part| part.part_number > part_number_marker);
+
+    let is_truncated = parts.len() > max_parts;
+    parts.truncate(max_parts);
+
+    Ok(ListPartsResult { parts, is_truncated })
+}
+
+#[derive(Debug)]
+pub struct ListMultipartUploadsOptions {
+    pub prefix: String,
+    pub key_marker: Option<String>,
+    pub upload_id_marker: Option<String>,
+    pub max_uploads: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ListMultipartUploadsResult {
+    pub uploads: Vec<MultipartUploadItem>,
+    pub is_truncated: bool,
+}
+
+/// 進行中のマルチパートアップロードを、キーとアップロードIDの順に返す
+pub async fn list_multipart_uploads(bucket: &str, options: ListMultipartUploadsOptions) -> Result<ListMultipartUploadsResult, Error> {
+    let base_path = format!("/{bucket}/");
+    let key_marker = options.key_marker.map(|marker| format!("{base_path}{marker}")).unwrap_or_default();
+
+    // upload-id-markerはkey-markerと同じキーのアップロードにのみ適用される
+    let upload_id_marker = options.upload_id_marker.filter(|_| !key_marker.is_empty());
+    let mut uploads = multipart::list_uploads_by_prefix(
+        &format!("{base_path}{}", options.prefix),
+        &key_marker,
+        upload_id_marker.as_deref(),
+        options.max_uploads as u64 + 1,
+    )
+    .await?;
+
+    let is_truncated = uploads.len() > options.max_uploads;
+    uploads.truncate(options.max_uploads);
+
+    Ok(ListMultipartUploadsResult { uploads, is_truncated })
+}
+
+/// 保存されているオブジェクトと、進行中のマルチパートアップロードの集計
+#[derive(Debug, Default)]
+pub struct StorageStatistics {
+    pub objects: i64,
+    /// オブジェクトの内容のサイズの合計。重複排除によって共有されているファイルもオブジェクトごとに数える
+    pub stored_bytes: i64,
+    pub multipart_uploads: u64,
+}
+
+pub async fn get_statistics() -> Result<StorageStatistics, Error> {
+    let (objects, stored_bytes) = metadata::get_statistics().await?;
+    let multipart_uploads = multipart::count_uploads().await?;
+
+    Ok(StorageStatistics {
+        objects,
+        stored_bytes,
+        multipart_uploads,
+    })
+}
+
+/// ディレクトリがあるファイルシステムの空き容量を返す
+pub fn available_space(path: &str) -> Result<u64, Error> {
+    file::available_space(Path::new(path))
+}
+
+pub async fn create_multipart_upload(
+    path: String,
+    filename: Option<String>,
+    encoded_filename: Option<String>,
+    mime_type: String,
+    headers: ObjectHeaders,
+) -> Result<String, Error> {
+    storage_root(&path)?;
+
     let upload_id = Uuid::new_v4().to_string();
-    let item = MultipartUploadItem {
-        path,
-        filename,
-        encoded_filename,
-        mime_type,
-        last_upload_at: Utc::now(),
+    let now = Utc::now().fixed_offset();
+    let item = entity::multipart_upload::ActiveModel {
+        upload_id: Set(upload_id.clone()),
+        path: Set(path),
+        filename: Set(filename),
+        encoded_filename: Set(encoded_filename),
+        mime_type: Set(mime_type),
+        created_at: Set(now),
+        last_upload_at: Set(now),
+        headers: Set(headers.to_json()),
     };
 
-    MULTIPART_UPLOAD_STATE.lock().unwrap().insert(upload_id.clone(), item);
+    multipart::create_upload(item).await?;
     tracing::debug!("Multipart upload created with ID: {}", upload_id);
 
     tokio::spawn(internal_cleanup());
-    upload_id
+    Ok(upload_id)
 }
 
-pub async fn upload_part(upload_id: String, number: u16, binary: BodyDataStream) -> Result<(), Error> {
-    {
-        let mut upload_item = MULTIPART_UPLOAD_STATE.lock().unwrap();
-        let item = upload_item.get_mut(&upload_id);
-        if item.is_none() {
-            return Err(anyhow::anyhow!("Invalid or expired uploadId: {}", upload_id));
-        }
-        item.unwrap().last_upload_at = Utc::now();
+/// パートを書き込み、パートのETagを返す
+pub async fn upload_part(upload_id: String, number: u16, binary: ObjectStream) -> Result<String, Error> {
+    let root = upload_root(&upload_id).await?;
+    if !multipart::touch_upload(&upload_id).await? {
+        return Err(S3Error::NoSuchUpload.into());
     }
 
-    file::write_object(format!("{upload_id}/{number}.part"), binary, true).await?;
-    Ok(())
+    // 同じパート番号で再送された場合は、書き込みが完了してから既存のパートを置き換える
+    let part_filename = format!("{upload_id}/{number}.part");
+    let written = file::write_object(&root, part_filename, binary, true).await?;
+    let part = entity::multipart_upload_part::ActiveModel {
+        upload_id: Set(upload_id),
+        part_number: Set(number as i32),
+        content_size: Set(written.size as i64),
+        updated_at: Set(Utc::now().fixed_offset()),
+        e_tag: Set(Some(written.md5.clone())),
+        ..Default::default()
+    };
+
+    multipart::upsert_part(part).await?;
+    Ok(written.md5)
 }
 
-pub async fn complete_multipart_upload(upload_id: String) -> Result<(), Error> {
-    let upload_item = {
-        let mut state = MULTIPART_UPLOAD_STATE.lock().unwrap();
-        state.remove(&upload_id)
+/// 既存のオブジェクトの全体または一部をパートとしてコピーする
+/// `range`は終端を含む (`x-amz-copy-source-range: bytes=first-last`と同じ) 範囲で指定する
+/// パートのETagを返す
+pub async fn upload_part_copy(upload_id: String, number: u16, source_path: String, range: Option<(u64, u64)>) -> Result<String, Error> {
+    let source = metadata::get_metadata_by_path(&source_path).await;
+    if source.is_none() {
+        return Err(S3Error::NoSuchKey.into());
+    }
+    let source = source.unwrap();
+
+    let range = match range {
+        Some((first, last)) => {
+            if first > last || last >= source.content_size as u64 {
+                return Err(
+                    S3Error::InvalidArgument(format!("Range specified is not valid for source object of size: {}", source.content_size)).into(),
+                );
+            }
+
+            Some(first..last + 1)
+        }
+        None => None,
+    };
+
+    let source_root = object_root(&source)?;
+    let root = upload_root(&upload_id).await?;
+    if !multipart::touch_upload(&upload_id).await? {
+        return Err(S3Error::NoSuchUpload.into());
+    }
+
+    let part_filename = format!("{upload_id}/{number}.part");
+    let written = file::copy_object(&source_root, source.internal_filename, &root, part_filename, range, true).await?;
+    let part = entity::multipart_upload_part::ActiveModel {
+        upload_id: Set(upload_id),
+        part_number: Set(number as i32),
+        content_size: Set(written.size as i64),
+        updated_at: Set(Utc::now().fixed_offset()),
+        e_tag: Set(Some(written.md5.clone())),
+        ..Default::default()
     };
 
+    multipart::upsert_part(part).await?;
+    Ok(written.md5)
+}
+
+/// CompleteMultipartUploadで指定されたパート
+#[derive(Debug)]
+pub struct CompletedPart {
+    pub part_number: i32,
+    pub e_tag: String,
+}
+
+/// 最後のパート以外のパートの最小サイズ
+const MIN_PART_SIZE: i64 = 5 * 1024 * 1024;
+
+/// パートを結合してオブジェクトを作成する
+/// アップロードはメタデータの作成と同じトランザクションで削除するため、途中で失敗した場合は同じアップロードIDで再試行できる
+pub async fn complete_multipart_upload(upload_id: String, completed_parts: Vec<CompletedPart>) -> Result<entity::object::Model, Error> {
+    let upload_item = multipart::get_upload(&upload_id).await;
     if upload_item.is_none() {
-        return Err(anyhow::anyhow!("Invalid or expired uploadId: {}", upload_id));
+        return Err(S3Error::NoSuchUpload.into());
     }
 
     let item = upload_item.unwrap();
-    let internal_filename = blake3::hash(item.path.as_bytes()).to_hex().to_string();
-    let file_size = file::merge_partial_uploads(&upload_id, &internal_filename.clone()).await?;
+    let uploaded_parts = multipart::list_parts(&upload_id).await?;
+    let parts = validate_completed_parts(&completed_parts, uploaded_parts)?;
+
+    let root = storage_root(&item.path)?;
+    let part_numbers = parts.iter().map(|part| part.part_number).collect::<Vec<_>>();
+    let temporary = file::merge_partial_uploads(&root, &upload_id, &part_numbers).await?;
+    let file_size = temporary.size;
+    let internal_filename = if config::CONFIG.bucket.dedupe {
+        store_blob(temporary).await?
+    } else {
+        let internal_filename = new_internal_filename(&item.path);
+        if let Err(e) = file::commit_temporary(&temporary, internal_filename.clone(), false).await {
+            file::remove_temporary(&temporary).await;
+            return Err(e);
+        }
+
+        internal_filename
+    };
+
+    let now = Utc::now().fixed_offset();
     let metadata = entity::object::ActiveModel {
-        internal_filename: Set(internal_filename),
+        internal_filename: Set(internal_filename.clone()),
         path: Set(item.path),
         filename: Set(item.filename),
         encoded_filename: Set(item.encoded_filename),
         content_size: Set(file_size as i64),
         mime_type: Set(item.mime_type),
+        updated_at: Set(Some(now)),
+        created_at: Set(Some(now)),
+        e_tag: Set(multipart_e_tag(&parts)),
+        headers: Set(item.headers),
         ..Default::default()
     };
 
-    metadata::create_metadata(metadata).await?;
-    file::delete_object(upload_id.clone(), true).await?;
+    let model = put_metadata(metadata, &root, &internal_filename, Some(&upload_id)).await?;
+    if let Err(e) = file::delete_object(&root, upload_id.clone(), true).await {
+        // アップロードは既に削除されているため、残ったディレクトリは次回の起動時に削除される
+        tracing::error!("Failed to remove multipart upload directory: {}", e);
+    }
 
     tracing::debug!("Multipart upload completed for ID: {}", upload_id);
-    Ok(())
+    Ok(model)
 }
 
-pub async fn abort_multipart_upload(upload_id: String) -> Result<(), Error> {
+/// 指定されたパートがアップロード済みのパートと一致するかを検証し、指定された順にパートを返す
+fn validate_completed_parts(
+    completed_parts: &[CompletedPart],
+    uploaded_parts: Vec<entity::multipart_upload_part::Model>,
+) -> Result<Vec<entity::multipart_upload_part::Model>, S3Error> {
+    if completed_parts.is_empty() {
+        return Err(S3Error::MalformedXML);
+    }
+
+    if completed_parts.windows(2).any(|pair| pair[0].part_number >= pair[1].part_number) {
+        return Err(S3Error::InvalidPartOrder);
+    }
+
+    let mut uploaded_parts = uploaded_parts.into_iter().map(|part| (part.part_number, part)).collect::<HashMap<_, _>>();
+    let mut parts = Vec::with_capacity(completed_parts.len());
+    for completed_part in completed_parts {
+        let part = uploaded_parts.remove(&completed_part.part_number).ok_or(S3Error::InvalidPart)?;
+
+        // ETagを記録する前にアップロードされたパートは内容を確認できないため、再度アップロードされるまで受け付けない
+        if part.e_tag.as_deref().is_none_or(|e_tag| completed_part.e_tag.trim_matches('"') != e_tag) {
+            return Err(S3Error::InvalidPart);
+        }
+
+        parts.push(part);
+    }
+
+    if parts
+        .split_last()
+        .is_some_and(|(_, others)| others.iter().any(|part| part.content_size < MIN_PART_SIZE))
     {
-        let mut state = MULTIPART_UPLOAD_STATE.lock().unwrap();
-        state.remove(&upload_id);
+        return Err(S3Error::EntityTooSmall);
     }
 
-    if let Err(e) = file::delete_object(upload_id.clone(), true).await {
+    Ok(parts)
+}
+
+/// パートのMD5から、マルチパートアップロードで作成したオブジェクトのETag (`md5-of-md5s-N`) を計算する
+/// ETagが記録されていないパートがある場合はNoneを返す
+fn multipart_e_tag(parts: &[entity::multipart_upload_part::Model]) -> Option<String> {
+    let mut hasher = Md5::new();
+    for part in parts {
+        let md5 = part.e_tag.as_deref()?;
+        let digest = (0..md5.len())
+            .step_by(2)
+            .map(|i| md5.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>()?;
+        hasher.update(digest);
+    }
+
+    Some(format!("{:x}-{}", hasher.finalize(), parts.len()))
+}
+
+pub async fn abort_multipart_upload(upload_id: String) -> Result<(), Error> {
+    let upload = multipart::delete_upload(&upload_id).await?;
+    let root = upload.map_or(Ok(PathBuf::from(&config::CONFIG.bucket.path)), |upload| storage_root(&upload.path))?;
+
+    if file::exists(&root, upload_id.clone(), true) &&
+        let Err(e) = file::delete_object(&root, upload_id.clone(), true).await
+    {
         tracing::error!("Failed to remove multipart upload directory: {}", e);
         return Err(e);
     }
@@ -187,17 +704,250 @@ pub async fn abort_multipart_upload(upload_id: String) -> Result<(), Error> {
 pub async fn delete_object(path: String) -> Result<(), Error> {
     let metadata = metadata::get_metadata_by_path(&path).await;
     if metadata.is_none() {
-        return Err(anyhow::anyhow!("Object metadata not found for path: {}", path));
+        return Err(S3Error::NoSuchKey.into());
     }
     let metadata = metadata.unwrap();
+    let internal_filename = metadata.internal_filename.clone();
+    let root = object_root(&metadata)?;
 
-    file::delete_object(metadata.internal_filename.clone(), false).await?;
-    metadata::delete_metadata(metadata).await?;
+    // 他のリクエストで置き換えられていた場合は、置き換えたリクエストが置き換え前のファイルを解放する
+    if metadata::delete_metadata(&metadata).await? {
+        release_object_file(&root, &internal_filename).await?;
+    }
 
     tracing::debug!("Object deleted successfully at path: {}", path);
     Ok(())
 }
 
+/// 複数のオブジェクトを削除する
+/// メタデータは1つのトランザクションで削除し、存在しないキーも削除済みとして扱う
+pub async fn delete_objects(paths: &[String]) -> Result<(), Error> {
+    let items = metadata::get_metadata_by_paths(paths).await?;
+    let items = metadata::delete_metadata_many(items).await?;
+
+    for item in items {
+        // メタデータは削除済みのため、ファイルの削除に失敗しても残るのはファイルのみとなる
+        let released = match object_root(&item) {
+            Ok(root) => release_object_file(&root, &item.internal_filename).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = released {
+            tracing::error!("Failed to remove object file for path {}: {}", item.path, e);
+        }
+    }
+
+    tracing::debug!("Deleted {} objects", paths.len());
+    Ok(())
+}
+
+/// オブジェクトのパス (`/bucket/key`) から、バケットのファイルを保存するディレクトリを返す
+fn storage_root(path: &str) -> Result<PathBuf, S3Error> {
+    let bucket = path.trim_start_matches('/').split('/').next().unwrap_or_default();
+    config::CONFIG
+        .find_bucket(bucket)
+        .map(|bucket| PathBuf::from(bucket.path))
+        .ok_or(S3Error::NoSuchBucket)
+}
+
+/// オブジェクトのファイルが保存されているディレクトリを返す
+/// `path`を指定したバケットを宣言する前に保存されたオブジェクトは`[bucket]`のディレクトリに残っているため、バケットのディレクトリにない場合はそちらを返す
+fn object_root(object: &entity::object::Model) -> Result<PathBuf, S3Error> {
+    let root = storage_root(&object.path)?;
+    let default_root = PathBuf::from(&config::CONFIG.bucket.path);
+    if root != default_root &&
+        !file::exists(&root, object.internal_filename.clone(), false) &&
+        file::exists(&default_root, object.internal_filename.clone(), false)
+    {
+        return Ok(default_root);
+    }
+
+    Ok(root)
+}
+
+/// マルチパートアップロードのパートを保存するバケットのディレクトリを返す
+async fn upload_root(upload_id: &str) -> Result<PathBuf, Error> {
+    let upload = multipart::get_upload(upload_id).await;
+    if upload.is_none() {
+        return Err(S3Error::NoSuchUpload.into());
+    }
+
+    Ok(storage_root(&upload.unwrap().path)?)
+}
+
+/// 新しく配置するファイルのinternal_filenameを生成する
+/// 上書き時に置き換え前のファイルと衝突しないよう、パスとUUIDから生成する
+fn new_internal_filename(path: &str) -> String {
+    blake3::hash(format!("{}:{}", path, Uuid::new_v4()).as_bytes()).to_hex().to_string()
+}
+
+/// メタデータを作成し、同じパスのオブジェクトが存在する場合は置き換える
+/// 置き換えた場合は置き換え前のファイルを解放し、作成に失敗した場合は新しく配置したファイルを解放する
+/// `upload_id`が指定された場合は、メタデータの作成と同時にマルチパートアップロードを削除する
+async fn put_metadata(
+    model: entity::object::ActiveModel,
+    root: &Path,
+    internal_filename: &str,
+    upload_id: Option<&str>,
+) -> Result<entity::object::Model, Error> {
+    let put_result = metadata::put_metadata(model, upload_id).await;
+    if let Err(e) = put_result {
+        // メタデータの作成に失敗した原因を返すため、ファイルの解放に失敗した場合はログに記録するのみとする
+        if let Err(release_error) = release_object_file(root, internal_filename).await {
+            tracing::warn!("Failed to release object file {}: {}", internal_filename, release_error);
+        }
+
+        return Err(e);
+    }
+
+    let (model, previous) = put_result.unwrap();
+    if let Some(previous) = previous {
+        let released = match object_root(&previous) {
+            Ok(previous_root) => release_object_file(&previous_root, &previous.internal_filename).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = released {
+            // メタデータは既に置き換わっているため、ファイルの削除に失敗してもリクエストは成功とする
+            tracing::error!("Failed to release overwritten object file {}: {}", previous.internal_filename, e);
+        }
+    }
+
+    Ok(model)
+}
+
+/// blobの名前を返す
+/// 別のディレクトリに保存するバケット同士ではファイルを共有できないため、`[bucket]`以外のディレクトリでは名前にディレクトリを含める
+fn blob_name(root: &Path, hash: &str) -> String {
+    if root == Path::new(&config::CONFIG.bucket.path) {
+        return hash.to_string();
+    }
+
+    blake3::hash(format!("{}:{}", root.display(), hash).as_bytes()).to_hex().to_string()
+}
+
+/// 一時ファイルを内容のハッシュを名前とするblobとして配置し、internal_filenameを返す
+/// 同じ内容のblobが既に存在する場合は一時ファイルを破棄して参照数のみを増やす
+async fn store_blob(temporary: file::TemporaryFile) -> Result<String, Error> {
+    let hash = blob_name(&temporary.root, &temporary.hash);
+
+    // 参照数を更新してからファイルの有無を確認するため、同じblobを解放している他のプロセスが削除したファイルを参照することはない
+    let is_committed = AtomicBool::new(false);
+    let acquire_result = blob::acquire_blob(&hash, temporary.size, || async {
+        if !file::exists(&temporary.root, hash.clone(), false) {
+            file::commit_temporary(&temporary, hash.clone(), false).await?;
+            is_committed.store(true, Ordering::SeqCst);
+        }
+
+        Ok(())
+    })
+    .await;
+
+    // 同じ内容のblobが既に存在する場合や、配置に失敗した場合は一時ファイルを破棄する
+    if !is_committed.load(Ordering::SeqCst) {
+        file::remove_temporary(&temporary).await;
+    }
+
+    acquire_result?;
+    Ok(hash)
+}
+
+/// コピー元のオブジェクトが参照するblobへの参照を増やし、internal_filenameを返す
+/// コピー元がblobとして管理されていない場合や、別のディレクトリのバケットにコピーする場合は内容をコピーしてblobを作成する
+async fn duplicate_blob(source: &entity::object::Model, source_root: &Path, root: &Path) -> Result<String, Error> {
+    if source_root == root && blob::reference_blob(&source.internal_filename).await? {
+        return Ok(source.internal_filename.clone());
+    }
+
+    let temporary = file::write_temporary(root, file::read_stream(source_root, source.internal_filename.clone())).await?;
+    store_blob(temporary).await
+}
+
+/// オブジェクトが参照しているファイルを解放する
+/// blobの場合は参照がなくなった時点でファイルを削除する
+async fn release_object_file(root: &Path, internal_filename: &str) -> Result<(), Error> {
+    let delete_file = || async {
+        if file::exists(root, internal_filename.to_string(), false) {
+            file::delete_object(root, internal_filename.to_string(), false).await?;
+        }
+
+        Ok(())
+    };
+
+    let remaining = blob::release_blob(internal_filename, delete_file).await?;
+    if remaining.is_none() {
+        // blobとして管理されていないファイルは、オブジェクトごとに固有のため参照数を確認せずに削除する
+        delete_file().await?;
+    }
+
+    Ok(())
+}
+
+/// バケット外のファイルを、パス (`/bucket/key`) のバケットにオブジェクトのファイルとして配置する
+pub async fn import_object_file(path: &str, source_path: &Path, internal_filename: String) -> Result<(), Error> {
+    file::import_file(&storage_root(path)?, source_path, internal_filename).await
+}
+
+/// 全てのバケットのオブジェクトのファイル数を数える
+pub async fn count_object_files() -> Result<u64, Error> {
+    let mut count = 0;
+    for storage_path in config::CONFIG.storage_paths() {
+        count += file::count_object_files(Path::new(&storage_path)).await?;
+    }
+
+    Ok(count)
+}
+
+/// オブジェクトのファイルを現在のシャーディングの設定に従った位置に移動し、移動したファイル数を返す
+pub async fn reshard_object_files(mut on_progress: impl FnMut()) -> Result<u64, Error> {
+    let mut moved = 0;
+    for storage_path in config::CONFIG.storage_paths() {
+        moved += file::reshard_object_files(Path::new(&storage_path), &mut on_progress).await?;
+    }
+
+    Ok(moved)
+}
+
+/// 作成日時・更新日時が記録されていないオブジェクトに、ファイルの更新日時を設定する
+/// 既に両方とも記録されている場合はfalseを返す
+pub async fn backfill_object_timestamps(object: &entity::object::Model) -> Result<bool, Error> {
+    if object.created_at.is_some() && object.updated_at.is_some() {
+        return Ok(false);
+    }
+
+    let root = object_root(object)?;
+    let modified_time = DateTime::<Utc>::from(file::modified_time(&root, object.internal_filename.clone()).await?).fixed_offset();
+    let mut model: entity::object::ActiveModel = object.clone().into();
+    model.created_at = Set(Some(object.created_at.unwrap_or(modified_time)));
+    model.updated_at = Set(Some(object.updated_at.unwrap_or(modified_time)));
+    metadata::update_metadata(model).await?;
+
+    Ok(true)
+}
+
+/// 既存のオブジェクトを内容のハッシュを名前とするblobに変換する
+/// 既に変換済みの場合はfalseを返す
+pub async fn convert_object_to_blob(object: &entity::object::Model) -> Result<bool, Error> {
+    if blob::get_blob(&object.internal_filename).await.is_some() {
+        return Ok(false);
+    }
+
+    // 新しい名前でファイルを参照できるようにしてからメタデータを切り替えるため、途中で中断しても元のファイルは失われない
+    let root = object_root(object)?;
+    let hash = blob_name(&root, &file::hash_object(&root, object.internal_filename.clone()).await?);
+    blob::assign_blob(object.id, &hash, object.content_size as u64, || async {
+        if file::exists(&root, hash.clone(), false) {
+            return Ok(());
+        }
+
+        file::link_object(&root, object.internal_filename.clone(), hash.clone()).await
+    })
+    .await?;
+    if object.internal_filename != hash {
+        file::delete_object(&root, object.internal_filename.clone(), false).await?;
+    }
+
+    Ok(true)
+}
+
 static IS_CLEANUP_REGISTERED: AtomicBool = AtomicBool::new(false);
 
 fn internal_cleanup() -> Pin<Box<dyn Future<Output = Result<(), ()>> + Send>> {
@@ -207,19 +957,18 @@ fn internal_cleanup() -> Pin<Box<dyn Future<Output = Result<(), ()>> + Send>> {
             return Ok(());
         }
 
-        let most_recent_item = {
-            let state = MULTIPART_UPLOAD_STATE.lock().unwrap();
-            let most_recent_item = state.values().min_by_key(|item| item.last_upload_at);
-
-            if most_recent_item.is_none() {
-                tracing::debug!("No cleanup needed, skipping...");
-                return Ok(());
-            }
-
-            most_recent_item.unwrap().clone()
+        let earliest_expires_at = match multipart::list_uploads().await {
+            Ok(uploads) => match uploads.iter().map(upload_expires_at).min() {
+                Some(expires_at) => expires_at,
+                None => {
+                    tracing::debug!("No cleanup needed, skipping...");
+                    return Ok(());
+                }
+            },
+            Err(_) => return Err(()),
         };
 
-        let exec_sec = config::CONFIG.bucket.request_expiration_seconds - (Utc::now() - most_recent_item.last_upload_at).num_seconds();
+        let exec_sec = (earliest_expires_at - Utc::now()).num_seconds();
         if exec_sec > 0 {
             tracing::debug!("Scheduling cleanup in {} seconds...", exec_sec);
             IS_CLEANUP_REGISTERED.store(true, Ordering::SeqCst);
@@ -227,22 +976,8 @@ fn internal_cleanup() -> Pin<Box<dyn Future<Output = Result<(), ()>> + Send>> {
         }
 
         tracing::debug!("Starting cleanup of expired multipart uploads...");
-        let expired_uploads = {
-            let state = MULTIPART_UPLOAD_STATE.lock().unwrap();
-            let now = Utc::now();
-            let diff = TimeDelta::seconds(config::CONFIG.bucket.request_expiration_seconds);
-            state
-                .iter()
-                .filter_map(|(id, item)| if now - item.last_upload_at > diff { Some(id.clone()) } else { None })
-                .collect::<Vec<String>>()
-        };
-
-        for upload_id in expired_uploads {
-            tracing::debug!("Removing expired multipart upload: {}", upload_id);
-            if let Err(e) = file::delete_object(upload_id.clone(), true).await {
-                tracing::error!("Failed to remove expired multipart upload {}: {}", upload_id, e);
-            }
-            MULTIPART_UPLOAD_STATE.lock().unwrap().remove(&upload_id);
+        if let Err(e) = remove_expired_multipart_uploads().await {
+            tracing::error!("Failed to cleanup expired multipart uploads: {}", e);
         }
 
         IS_CLEANUP_REGISTERED.store(false, Ordering::SeqCst);
@@ -252,3 +987,244 @@ fn internal_cleanup() -> Pin<Box<dyn Future<Output = Result<(), ()>> + Send>> {
         Ok(())
     })
 }
+
+/// マルチパートアップロードの有効期限を返す
+/// 設定から削除されたバケットのアップロードは`[bucket]`の設定で期限切れにする
+fn upload_expires_at(upload: &MultipartUploadItem) -> DateTime<Utc> {
+    let bucket = upload.path.trim_start_matches('/').split('/').next().unwrap_or_default();
+    let expiration_seconds = config::CONFIG
+        .find_bucket(bucket)
+        .map_or(config::CONFIG.bucket.request_expiration_seconds, |bucket| {
+            bucket.request_expiration_seconds
+        });
+
+    upload.last_upload_at.to_utc() + TimeDelta::seconds(expiration_seconds)
+}
+
+async fn remove_expired_multipart_uploads() -> Result<(), Error> {
+    let now = Utc::now();
+    let expired_uploads = multipart::list_uploads()
+        .await?
+        .into_iter()
+        .filter(|upload| upload_expires_at(upload) < now);
+
+    for upload in expired_uploads {
+        tracing::debug!("Removing expired multipart upload: {}", upload.upload_id);
+        multipart::delete_upload(&upload.upload_id).await?;
+        let root = storage_root(&upload.path).unwrap_or(PathBuf::from(&config::CONFIG.bucket.path));
+        if file::exists(&root, upload.upload_id.clone(), true) &&
+            let Err(e) = file::delete_object(&root, upload.upload_id.clone(), true).await
+        {
+            tracing::error!("Failed to remove expired multipart upload {}: {}", upload.upload_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uploaded_part(part_number: i32, content_size: i64) -> entity::multipart_upload_part::Model {
+        entity::multipart_upload_part::Model {
+            id: part_number,
+            upload_id: "upload".to_string(),
+            part_number,
+            content_size,
+            updated_at: Utc::now().fixed_offset(),
+            e_tag: Some(format!("{part_number:032x}")),
+        }
+    }
+
+    fn completed_part(part_number: i32) -> CompletedPart {
+        CompletedPart {
+            part_number,
+            e_tag: format!("\"{part_number:032x}\""),
+        }
+    }
+
+    fn validate(part_numbers: &[i32], uploaded_parts: Vec<entity::multipart_upload_part::Model>) -> Result<Vec<i32>, S3Error> {
+        let completed_parts = part_numbers.iter().map(|&part_number| completed_part(part_number)).collect::<Vec<_>>();
+        let parts = validate_completed_parts(&completed_parts, uploaded_parts)?;
+        Ok(parts.iter().map(|part| part.part_number).collect())
+    }
+
+    fn object(path: &str) -> entity::object::Model {
+        entity::object::Model {
+            id: 0,
+            path: path.to_string(),
+            content_size: 0,
+            mime_type: "application/octet-stream".to_string(),
+            internal_filename: path.to_string(),
+            encoded_filename: None,
+            filename: None,
+            updated_at: None,
+            created_at: None,
+            e_tag: None,
+            headers: None,
+        }
+    }
+
+    /// キーの一覧から、DBと同じくバイト順に並べたオブジェクトを2件ずつ読み込んで一覧を作成する
+    async fn list(keys: &[&str], prefix: &str, delimiter: Option<&str>, start_after: Option<&str>, max_keys: usize) -> ListObjectsResult {
+        let mut objects = keys.iter().map(|key| object(&format!("/bucket/{key}"))).collect::<Vec<_>>();
+        objects.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let options = ListObjectsOptions {
+            prefix: prefix.to_string(),
+            delimiter: delimiter.map(|d| d.to_string()),
+            start_after: start_after.map(|s| s.to_string()),
+            max_keys,
+        };
+        let fetch = |prefix: String, after: String, limit: u64| {
+            let items = objects
+                .iter()
+                .filter(|object| object.path.starts_with(&prefix) && object.path > after)
+                .take(limit as usize)
+                .cloned()
+                .collect::<Vec<_>>();
+            async move { Ok(items) }
+        };
+
+        collect_objects("bucket", options, 2, fetch).await.unwrap()
+    }
+
+    fn keys(result: &ListObjectsResult) -> Vec<&str> {
+        result.objects.iter().map(|object| &object.path["/bucket/".len()..]).collect()
+    }
+
+    /// `next_marker`を次のページの`start_after`として、最後のページまで一覧を取得する
+    async fn list_pages(keys: &[&str], prefix: &str, delimiter: Option<&str>, max_keys: usize) -> Vec<ListObjectsResult> {
+        let mut pages = Vec::new();
+        let mut start_after = None;
+        loop {
+            let page = list(keys, prefix, delimiter, start_after.as_deref(), max_keys).await;
+            start_after = page.next_marker.clone();
+            pages.push(page);
+            if start_after.is_none() {
+                return pages;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_objects_across_batches() {
+        let result = list(&["e", "a", "c", "b", "d"], "", None, None, 1000).await;
+        assert_eq!(keys(&result), ["a", "b", "c", "d", "e"]);
+        assert!(!result.is_truncated);
+        assert_eq!(result.next_marker, None);
+
+        let result = list(&["a", "b"], "", None, None, 0).await;
+        assert!(result.objects.is_empty());
+    }
+
+    #[tokio::test]
+    async fn paginates_with_next_marker() {
+        let pages = list_pages(&["a", "b", "c", "d", "e"], "", None, 2).await;
+        assert_eq!(pages.iter().map(keys).collect::<Vec<_>>(), [vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+        assert_eq!(pages.iter().map(|page| page.is_truncated).collect::<Vec<_>>(), [true, true, false]);
+        assert_eq!(pages[0].next_marker.as_deref(), Some("b"));
+
+        // 最後のキーでちょうどmax_keysに達した場合は続きがない
+        let pages = list_pages(&["a", "b", "c", "d"], "", None, 2).await;
+        assert_eq!(pages.len(), 2);
+        assert!(!pages[1].is_truncated);
+    }
+
+    #[tokio::test]
+    async fn starts_after_the_given_key() {
+        let result = list(&["a", "b", "b0", "c"], "", None, Some("b"), 1000).await;
+        assert_eq!(keys(&result), ["b0", "c"]);
+    }
+
+    #[tokio::test]
+    async fn groups_common_prefixes_across_pages() {
+        let objects = ["a.txt", "dir1/x", "dir1/y", "dir1/z", "dir2/x", "dir2/y/z", "e.txt"];
+        let pages = list_pages(&objects, "", Some("/"), 2).await;
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(keys(&pages[0]), ["a.txt"]);
+        assert_eq!(pages[0].common_prefixes, ["dir1/"]);
+        assert_eq!(pages[0].next_marker.as_deref(), Some("dir1/"));
+
+        // 前のページで返した共通プレフィックス配下のキーは、次のページで再び返さない
+        assert_eq!(pages[1].common_prefixes, ["dir2/"]);
+        assert_eq!(keys(&pages[1]), ["e.txt"]);
+        assert!(!pages[1].is_truncated);
+    }
+
+    #[tokio::test]
+    async fn groups_common_prefixes_under_prefix() {
+        let objects = ["dir1/a/1", "dir1/a/2", "dir1/a/3", "dir1/b", "dir1/c/1", "dir10/d", "dir2/e"];
+        let result = list(&objects, "dir1/", Some("/"), None, 1000).await;
+        assert_eq!(result.common_prefixes, ["dir1/a/", "dir1/c/"]);
+        assert_eq!(keys(&result), ["dir1/b"]);
+
+        let pages = list_pages(&objects, "dir1/", Some("/"), 1).await;
+        assert_eq!(pages.len(), 3);
+        assert_eq!(
+            pages.iter().map(|page| page.next_marker.as_deref()).collect::<Vec<_>>(),
+            [Some("dir1/a/"), Some("dir1/b"), None]
+        );
+    }
+
+    #[test]
+    fn accepts_parts_in_ascending_order() {
+        let uploaded_parts = vec![uploaded_part(1, MIN_PART_SIZE), uploaded_part(2, MIN_PART_SIZE), uploaded_part(3, 1)];
+        assert_eq!(validate(&[1, 2, 3], uploaded_parts.clone()), Ok(vec![1, 2, 3]));
+
+        // アップロード済みのパートの一部のみを指定することもできる
+        assert_eq!(validate(&[1, 3], uploaded_parts), Ok(vec![1, 3]));
+    }
+
+    #[test]
+    fn rejects_empty_part_list() {
+        assert_eq!(validate(&[], vec![uploaded_part(1, 1)]), Err(S3Error::MalformedXML));
+    }
+
+    #[test]
+    fn rejects_parts_out_of_order() {
+        let uploaded_parts = vec![uploaded_part(1, MIN_PART_SIZE), uploaded_part(2, 1)];
+        assert_eq!(validate(&[2, 1], uploaded_parts.clone()), Err(S3Error::InvalidPartOrder));
+        assert_eq!(validate(&[1, 1, 2], uploaded_parts), Err(S3Error::InvalidPartOrder));
+    }
+
+    #[test]
+    fn rejects_missing_parts() {
+        let uploaded_parts = vec![uploaded_part(1, MIN_PART_SIZE), uploaded_part(2, 1)];
+        assert_eq!(validate(&[1, 3], uploaded_parts), Err(S3Error::InvalidPart));
+    }
+
+    #[test]
+    fn rejects_mismatched_e_tag() {
+        let uploaded_parts = vec![uploaded_part(1, MIN_PART_SIZE), uploaded_part(2, 1)];
+        let completed_parts = vec![
+            completed_part(1),
+            CompletedPart {
+                part_number: 2,
+                e_tag: format!("\"{:032x}\"", 3),
+            },
+        ];
+        assert_eq!(
+            validate_completed_parts(&completed_parts, uploaded_parts).unwrap_err(),
+            S3Error::InvalidPart
+        );
+    }
+
+    #[test]
+    fn rejects_parts_without_e_tag() {
+        let mut uploaded_parts = vec![uploaded_part(1, MIN_PART_SIZE), uploaded_part(2, 1)];
+        uploaded_parts[0].e_tag = None;
+        assert_eq!(validate(&[1, 2], uploaded_parts), Err(S3Error::InvalidPart));
+    }
+
+    #[test]
+    fn rejects_small_parts_except_last() {
+        let uploaded_parts = vec![uploaded_part(1, MIN_PART_SIZE - 1), uploaded_part(2, MIN_PART_SIZE)];
+        assert_eq!(validate(&[1, 2], uploaded_parts.clone()), Err(S3Error::EntityTooSmall));
+
+        // 最後のパートは5MiB未満でもよい
+        assert_eq!(validate(&[1], uploaded_parts), Ok(vec![1]));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::database;
+use anyhow::Error;
+use chrono::Utc;
+use sea_orm::{
+    ActiveValue::Set,
+    ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, TransactionTrait,
+    sea_query::{Expr, OnConflict},
+};
+
+// blobの行とファイルの対応が崩れないよう、参照数は常にSQLで直接増減し、ファイルの配置・削除は行をロックしたトランザクションの中で行う
+// 別のプロセスが同じblobの参照数を更新する場合はコミットまで待たされるため、プロセスをまたいでも競合しない
+
+pub async fn get_blob(hash: &str) -> Option<entity::blob::Model> {
+    let blob = entity::blob::Entity::find_by_id(hash).one(database::get_db()).await;
+
+    if let Err(e) = blob {
+        tracing::error!("Failed to fetch blob '{}': {}", hash, e);
+        return None;
+    }
+
+    blob.unwrap()
+}
+
+/// blobの参照を1つ増やす。blobが存在しない場合は参照数1で作成する
+/// `on_acquired`は参照数を更新したトランザクションの中で呼び出すため、失敗した場合は参照数の更新も取り消される
+pub async fn acquire_blob<F, Fut>(hash: &str, content_size: u64, on_acquired: F) -> Result<(), Error>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    let txn = database::get_db().begin().await?;
+    increment_ref_count(&txn, hash, content_size).await?;
+    on_acquired().await?;
+
+    if let Err(e) = txn.commit().await {
+        tracing::error!("Failed to acquire blob '{}': {}", hash, e);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// 既に存在するblobの参照を1つ増やす。blobが存在しない場合はfalseを返す
+pub async fn reference_blob(hash: &str) -> Result<bool, Error> {
+    let update_result = entity::blob::Entity::update_many()
+        .col_expr(entity::blob::Column::RefCount, Expr::col(entity::blob::Column::RefCount).add(1))
+        .filter(entity::blob::Column::Hash.eq(hash))
+        .exec(database::get_db())
+        .await;
+
+    if let Err(e) = update_result {
+        tracing::error!("Failed to reference blob '{}': {}", hash, e);
+        return Err(e.into());
+    }
+
+    Ok(update_result.unwrap().rows_affected > 0)
+}
+
+/// blobの参照を1つ減らし、残りの参照数を返す。blobとして管理されていない場合はNoneを返す
+/// 参照がなくなったblobの行は削除し、同じトランザクションの中で`on_released`を呼び出す
+pub async fn release_blob<F, Fut>(hash: &str, on_released: F) -> Result<Option<i64>, Error>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    let txn = database::get_db().begin().await?;
+
+    // 更新した行はコミットまでロックされるため、続けて読み込んだ参照数は他のプロセスの更新と競合しない
+    entity::blob::Entity::update_many()
+        .col_expr(entity::blob::Column::RefCount, Expr::col(entity::blob::Column::RefCount).sub(1))
+        .filter(entity::blob::Column::Hash.eq(hash))
+        .exec(&txn)
+        .await?;
+
+    let blob = entity::blob::Entity::find_by_id(hash).one(&txn).await?;
+    if blob.is_none() {
+        txn.rollback().await?;
+        return Ok(None);
+    }
+
+    let ref_count = blob.unwrap().ref_count;
+    if ref_count <= 0 {
+        entity::blob::Entity::delete_by_id(hash).exec(&txn).await?;
+        on_released().await?;
+    }
+
+    if let Err(e) = txn.commit().await {
+        tracing::error!("Failed to release blob '{}': {}", hash, e);
+        return Err(e.into());
+    }
+
+    Ok(Some(ref_count.max(0)))
+}
+
+/// オブジェクトの参照先をblobに切り替える
+/// blobの参照の追加とオブジェクトの更新は1つのトランザクションで行い、`on_acquired`もその中で呼び出す
+pub async fn assign_blob<F, Fut>(object_id: i32, hash: &str, content_size: u64, on_acquired: F) -> Result<(), Error>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    let txn = database::get_db().begin().await?;
+    increment_ref_count(&txn, hash, content_size).await?;
+    on_acquired().await?;
+
+    entity::object::Entity::update(entity::object::ActiveModel {
+        id: Set(object_id),
+        internal_filename: Set(hash.to_string()),
+        ..Default::default()
+    })
+    .exec(&txn)
+    .await?;
+
+    if let Err(e) = txn.commit().await {
+        tracing::error!("Failed to assign blob '{}' to object {}: {}", hash, object_id, e);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// blobの参照数を1つ増やす。blobが存在しない場合は参照数1で作成する
+async fn increment_ref_count(txn: &DatabaseTransaction, hash: &str, content_size: u64) -> Result<(), Error> {
+    let blob = entity::blob::ActiveModel {
+        hash: Set(hash.to_string()),
+        content_size: Set(content_size as i64),
+        ref_count: Set(1),
+        created_at: Set(Utc::now().fixed_offset()),
+    };
+
+    entity::blob::Entity::insert(blob)
+        .on_conflict(
+            OnConflict::column(entity::blob::Column::Hash)
+                .value(
+                    entity::blob::Column::RefCount,
+                    Expr::col((entity::blob::Entity, entity::blob::Column::RefCount)).add(1),
+                )
+                .to_owned(),
+        )
+        .exec_without_returning(txn)
+        .await?;
+
+    Ok(())
+}
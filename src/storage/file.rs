@@ -1,21 +1,70 @@
 use anyhow::Error;
-use axum::body::BodyDataStream;
-use std::path::{Path, PathBuf};
+use async_recursion::async_recursion;
+use bytes::Bytes;
+use futures::stream;
+use md5::{Digest, Md5};
+use std::{
+    io::SeekFrom,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 use tokio::{
-    fs::{self, File, OpenOptions},
-    io::{self, AsyncWriteExt, BufWriter},
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter},
 };
 use tokio_stream::StreamExt;
+use uuid::Uuid;
+
+use crate::{config, storage::ObjectStream};
+
+const TEMPORARY_DIR: &str = ".tmp";
+const MULTIPART_DIR: &str = ".multipart";
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+/// シャーディングの最大階層数
+const MAX_SHARD_DEPTH: usize = 4;
+/// シャーディングの1階層あたりのディレクトリ名の文字数
+const SHARD_WIDTH: usize = 2;
+/// 一時ファイルが書き込み途中のものではなく、残ったものとみなすまでの時間
+const TEMPORARY_EXPIRATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 一時ディレクトリに書き込まれたファイル
+/// `hash`は内容のblake3ハッシュで、内容アドレス方式のblob名として使用する
+#[derive(Debug)]
+pub struct TemporaryFile {
+    /// 一時ファイルを書き込んだバケットのディレクトリ
+    /// 配置先と同じファイルシステム上でrenameできるよう、一時ファイルはバケットのディレクトリごとに作成する
+    pub root: PathBuf,
+    pub name: String,
+    pub size: u64,
+    pub hash: String,
+    pub md5: String,
+}
 
-use crate::config;
+/// 書き込んだオブジェクトのサイズと、ETagとして使用する内容のMD5
+#[derive(Debug)]
+pub struct WrittenObject {
+    pub size: u64,
+    pub md5: String,
+}
 
-pub async fn read_object(internal_filename: String) -> Result<File, Error> {
-    let path = resolve_path(internal_filename, false);
-    if !path.exists() {
+pub async fn read_object(root: &Path, internal_filename: String) -> Result<File, Error> {
+    let path = locate_path(root, &internal_filename);
+    if path.is_none() {
         return Err(anyhow::anyhow!("File does not exist"));
     }
 
-    let file = File::open(&path).await;
+    let path = path.unwrap();
+    let mut file = File::open(&path).await;
+
+    // reshardによって開く直前にファイルが移動された場合は、移動先を探し直す
+    if let Err(e) = &file &&
+        e.kind() == std::io::ErrorKind::NotFound &&
+        let Some(path) = locate_path(root, &internal_filename)
+    {
+        file = File::open(&path).await;
+    }
+
     if file.is_err() {
         return Err(anyhow::anyhow!("Failed to open file: {}", file.as_ref().err().unwrap()));
     }
@@ -24,85 +73,259 @@ pub async fn read_object(internal_filename: String) -> Result<File, Error> {
     Ok(file.unwrap())
 }
 
-pub async fn write_object(internal_filename: String, mut stream: BodyDataStream, is_multipart: bool) -> Result<(), Error> {
-    let path = resolve_path(internal_filename, is_multipart);
-    if path.exists() {
-        return Err(anyhow::anyhow!("File already exists at path: {}", path.display()));
+pub fn exists(root: &Path, internal_filename: String, is_multipart: bool) -> bool {
+    if is_multipart {
+        return resolve_path(root, internal_filename, true).exists();
     }
 
-    if is_multipart &&
-        let Some(parent) = path.parent() &&
-        !parent.exists()
-    {
-        fs::create_dir_all(parent).await?;
+    locate_path(root, &internal_filename).is_some()
+}
+
+/// ストリームを一時ファイルに書き込み、fsyncしてから所定の位置にrenameする
+/// 途中で失敗した場合や接続が切れた場合でも、書き込み先に中途半端なファイルは残らない
+/// パートは同じ番号で再送された場合に既存のファイルを置き換える
+pub async fn write_object(root: &Path, internal_filename: String, stream: ObjectStream, is_multipart: bool) -> Result<WrittenObject, Error> {
+    if !is_multipart && exists(root, internal_filename.clone(), false) {
+        return Err(anyhow::anyhow!("File already exists: {}", internal_filename));
     }
 
-    let mut writer = BufWriter::new(File::create(&path).await?);
-    while let Some(chunk) = stream.next().await {
-        writer.write_all(&chunk?).await?;
+    let temporary = write_temporary(root, stream).await?;
+    if let Err(e) = commit_temporary(&temporary, internal_filename, is_multipart).await {
+        remove_temporary(&temporary).await;
+        return Err(e);
+    }
+
+    Ok(WrittenObject {
+        size: temporary.size,
+        md5: temporary.md5,
+    })
+}
+
+/// オブジェクトをサーバー内でコピーする
+/// `range`が指定された場合はその範囲のみをコピーする
+pub async fn copy_object(
+    source_root: &Path,
+    source_filename: String,
+    root: &Path,
+    destination_filename: String,
+    range: Option<Range<u64>>,
+    is_multipart: bool,
+) -> Result<WrittenObject, Error> {
+    let source_path = locate_path(source_root, &source_filename).ok_or_else(|| anyhow::anyhow!("File does not exist: {}", source_filename))?;
+    let written = write_object(root, destination_filename.clone(), stream_file(source_path.clone(), range), is_multipart).await?;
+
+    tracing::debug!("Object copied from {} to {}", source_path.display(), destination_filename);
+    Ok(written)
+}
+
+/// 一時ディレクトリ内に新しいファイルのパスを作成する
+async fn create_temporary_path(root: &Path) -> Result<PathBuf, Error> {
+    let temporary_dir = root.join(TEMPORARY_DIR);
+    if !temporary_dir.exists() {
+        fs::create_dir_all(&temporary_dir).await?;
+    }
+
+    Ok(temporary_dir.join(Uuid::new_v4().to_string()))
+}
+
+/// ストリームを一時ファイルに書き込み、書き込みと同時に内容のハッシュを計算する
+/// 書き込んだ内容はfsyncされた状態で返す
+pub async fn write_temporary(root: &Path, mut stream: ObjectStream) -> Result<TemporaryFile, Error> {
+    let path = create_temporary_path(root).await?;
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+    let result: Result<(u64, String, String), Error> = async {
+        let mut writer = BufWriter::new(File::create(&path).await?);
+        let mut hasher = blake3::Hasher::new();
+        let mut md5 = Md5::new();
+        let mut written_size = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
+            md5.update(&chunk);
+            writer.write_all(&chunk).await?;
+            written_size += chunk.len() as u64;
+        }
+
+        writer.flush().await?;
+        writer.get_ref().sync_all().await?;
+        Ok((written_size, hasher.finalize().to_hex().to_string(), format!("{:x}", md5.finalize())))
+    }
+    .await;
+
+    if let Err(e) = result {
+        if let Err(e) = fs::remove_file(&path).await {
+            tracing::error!("Failed to remove temporary file {}: {}", path.display(), e);
+        }
+
+        return Err(e);
+    }
+
+    let (size, hash, md5) = result.unwrap();
+    Ok(TemporaryFile {
+        root: root.to_path_buf(),
+        name,
+        size,
+        hash,
+        md5,
+    })
+}
+
+/// 一時ファイルを、一時ファイルを書き込んだバケットのディレクトリにオブジェクトとして配置する
+/// パートの場合は、同じ番号のパートが存在していてもrenameで置き換える
+pub async fn commit_temporary(temporary: &TemporaryFile, internal_filename: String, is_multipart: bool) -> Result<(), Error> {
+    let temporary_path = temporary.root.join(TEMPORARY_DIR).join(&temporary.name);
+    if !is_multipart && exists(&temporary.root, internal_filename.clone(), false) {
+        return Err(anyhow::anyhow!("File already exists: {}", internal_filename));
     }
 
-    writer.flush().await?;
+    let path = resolve_path(&temporary.root, internal_filename, is_multipart);
+    rename_into_place(&temporary_path, &path).await?;
 
-    tracing::debug!("Object written successfully to path: {}", path.display());
+    tracing::debug!("Temporary file {} committed to path: {}", temporary.name, path.display());
     Ok(())
 }
 
-pub async fn merge_partial_uploads(upload_id: &str, internal_filename: &str) -> Result<u64, Error> {
-    let object_path = resolve_path(internal_filename.to_owned(), false);
-    let multipart_path = resolve_path(upload_id.to_owned(), true);
-    let temporary_output_path = multipart_path.join("object-merged.tmp");
+pub async fn remove_temporary(temporary: &TemporaryFile) {
+    let temporary_path = temporary.root.join(TEMPORARY_DIR).join(&temporary.name);
+    if let Err(e) = fs::remove_file(&temporary_path).await {
+        tracing::error!("Failed to remove temporary file {}: {}", temporary_path.display(), e);
+    }
+}
 
-    if !multipart_path.exists() {
-        return Err(anyhow::anyhow!("Multipart upload path does not exist: {}", multipart_path.display()));
+/// 前回の実行時に残った一時ファイルを削除する
+/// 同じディレクトリを使用している他のプロセスが書き込み中のファイルを消さないよう、一定時間更新されていないファイルのみを削除する
+pub async fn clear_temporary(root: &Path) -> Result<(), Error> {
+    let temporary_dir = root.join(TEMPORARY_DIR);
+    if !temporary_dir.exists() {
+        return Ok(());
     }
 
-    if object_path.exists() {
-        return Err(anyhow::anyhow!("File already exists at path: {}", object_path.display()));
+    let mut entries = fs::read_dir(&temporary_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let modified = entry.metadata().await?.modified()?;
+        let is_stale = modified.elapsed().is_ok_and(|elapsed| elapsed >= TEMPORARY_EXPIRATION);
+        if !is_stale {
+            continue;
+        }
+
+        let path = entry.path();
+        tracing::debug!("Removing stale temporary file: {}", path.display());
+        if let Err(e) = fs::remove_file(&path).await {
+            tracing::error!("Failed to remove temporary file {}: {}", path.display(), e);
+        }
     }
 
-    let mut multipart_files = fs::read_dir(&multipart_path).await?;
-    let mut file_list = Vec::new();
+    Ok(())
+}
 
-    while let Some(entry) = multipart_files.next_entry().await? {
-        if entry.file_type().await?.is_file() {
-            file_list.push(entry.path());
+/// オブジェクトの内容を読み込むストリームを返す
+pub fn read_stream(root: &Path, internal_filename: String) -> ObjectStream {
+    let path = locate_path(root, &internal_filename).unwrap_or_else(|| resolve_path(root, internal_filename, false));
+    stream_file(path, None)
+}
+
+/// ファイルを読み込むストリームを返す
+/// `range`が指定された場合はその範囲のみを読み込む
+fn stream_file(path: PathBuf, range: Option<Range<u64>>) -> ObjectStream {
+    Box::pin(stream::try_unfold((path, range, None::<File>), |(path, range, file)| async move {
+        let mut file = match file {
+            Some(file) => file,
+            None => {
+                let mut file = File::open(&path).await?;
+                if let Some(range) = &range {
+                    file.seek(SeekFrom::Start(range.start)).await?;
+                }
+
+                file
+            }
+        };
+
+        let remaining = range.as_ref().map_or(READ_BUFFER_SIZE as u64, |range| range.end - range.start);
+        let mut buffer = vec![0; remaining.min(READ_BUFFER_SIZE as u64) as usize];
+        let read_size = file.read(&mut buffer).await?;
+        if read_size == 0 {
+            if range.as_ref().is_some_and(|range| range.start < range.end) {
+                return Err(anyhow::anyhow!("Unexpected end of file: {}", path.display()));
+            }
+
+            return Ok(None);
         }
+
+        buffer.truncate(read_size);
+        let range = range.map(|range| range.start + read_size as u64..range.end);
+        Ok(Some((Bytes::from(buffer), (path, range, Some(file)))))
+    }))
+}
+
+/// オブジェクトのファイルの更新日時を返す
+pub async fn modified_time(root: &Path, internal_filename: String) -> Result<SystemTime, Error> {
+    let path = locate_path(root, &internal_filename).ok_or_else(|| anyhow::anyhow!("File does not exist: {}", internal_filename))?;
+    Ok(fs::metadata(&path).await?.modified()?)
+}
+
+/// オブジェクトの内容のblake3ハッシュを計算する
+pub async fn hash_object(root: &Path, internal_filename: String) -> Result<String, Error> {
+    let mut stream = read_stream(root, internal_filename);
+    let mut hasher = blake3::Hasher::new();
+    while let Some(chunk) = stream.next().await {
+        hasher.update(&chunk?);
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// オブジェクトを別名で参照できるようにする
+/// ハードリンクを作成できない場合はコピーする
+pub async fn link_object(root: &Path, source_filename: String, destination_filename: String) -> Result<(), Error> {
+    let source_path = locate_path(root, &source_filename).ok_or_else(|| anyhow::anyhow!("File does not exist: {}", source_filename))?;
+    if exists(root, destination_filename.clone(), false) {
+        return Err(anyhow::anyhow!("File already exists: {}", destination_filename));
     }
 
-    file_list.sort_by(|a, b| {
-        let a_name = a.file_name().unwrap().to_str().unwrap().replace(".part", "");
-        let b_name = b.file_name().unwrap().to_str().unwrap().replace(".part", "");
-        let a_num = a_name.parse::<u64>().unwrap_or(0);
-        let b_num = b_name.parse::<u64>().unwrap_or(0);
-        a_num.cmp(&b_num)
-    });
+    let destination_path = resolve_path(root, destination_filename, false);
+    create_parent_dir(&destination_path).await?;
+    if let Err(e) = fs::hard_link(&source_path, &destination_path).await {
+        tracing::debug!("Failed to create hard link, falling back to copy: {}", e);
+        fs::copy(&source_path, &destination_path).await?;
+    }
 
-    let output_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&temporary_output_path)
-        .await?;
+    Ok(())
+}
 
-    let mut writer = BufWriter::new(output_file);
+/// 指定されたパートを指定された順に結合して一時ファイルに書き込む
+pub async fn merge_partial_uploads(root: &Path, upload_id: &str, part_numbers: &[i32]) -> Result<TemporaryFile, Error> {
+    let multipart_path = resolve_path(root, upload_id.to_owned(), true);
 
-    for file in file_list {
-        let mut input_file = File::open(file).await?;
-        io::copy(&mut input_file, &mut writer).await?;
+    if !multipart_path.exists() {
+        return Err(anyhow::anyhow!("Multipart upload path does not exist: {}", multipart_path.display()));
     }
 
-    writer.flush().await?;
-    fs::rename(&temporary_output_path, &object_path).await?;
+    let mut file_list = Vec::new();
+    for part_number in part_numbers {
+        let path = multipart_path.join(format!("{part_number}.part"));
+        if !path.is_file() {
+            return Err(anyhow::anyhow!("Part file does not exist: {}", path.display()));
+        }
+
+        file_list.push(path);
+    }
 
-    tracing::debug!("Merged multipart uploads into object at path: {}", object_path.display());
-    return Ok(fs::metadata(&object_path).await?.len());
+    let merged_stream = futures::StreamExt::flatten(stream::iter(file_list.into_iter().map(|path| stream_file(path, None))));
+    let temporary = write_temporary(root, Box::pin(merged_stream)).await?;
+
+    tracing::debug!("Merged multipart uploads into temporary file: {}", temporary.name);
+    Ok(temporary)
 }
 
-pub async fn delete_object(internal_path: String, is_multipart: bool) -> Result<(), Error> {
-    let path = resolve_path(internal_path, is_multipart);
+pub async fn delete_object(root: &Path, internal_path: String, is_multipart: bool) -> Result<(), Error> {
+    let path = if is_multipart {
+        Some(resolve_path(root, internal_path.clone(), true))
+    } else {
+        locate_path(root, &internal_path)
+    };
 
-    if is_multipart {
+    let path = path.ok_or_else(|| anyhow::anyhow!("File does not exist: {}", internal_path))?;
+    if is_multipart && path.is_dir() {
         fs::remove_dir_all(&path).await?;
     } else {
         if !path.exists() {
@@ -116,11 +339,219 @@ pub async fn delete_object(internal_path: String, is_multipart: bool) -> Result<
     Ok(())
 }
 
-fn resolve_path(internal_path: String, is_multipart: bool) -> PathBuf {
-    let base = Path::new(&config::CONFIG.bucket.path);
+/// バケット外のファイルをオブジェクトとして配置する
+pub async fn import_file(root: &Path, source_path: &Path, internal_filename: String) -> Result<(), Error> {
+    if exists(root, internal_filename.clone(), false) {
+        return Err(anyhow::anyhow!("File already exists: {}", internal_filename));
+    }
+
+    let path = resolve_path(root, internal_filename, false);
+    create_parent_dir(&path).await?;
+    fs::rename(source_path, &path).await?;
+    Ok(())
+}
+
+/// バケット内のオブジェクトのファイル数を数える
+pub async fn count_object_files(root: &Path) -> Result<u64, Error> {
+    let mut walker = ObjectFileWalker::new(root);
+    let mut count = 0;
+    while walker.next().await?.is_some() {
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// バケットのディレクトリがあるファイルシステムの空き容量を返す
+pub fn available_space(root: &Path) -> Result<u64, Error> {
+    Ok(fs4::available_space(root)?)
+}
+
+/// 現在のシャーディングの設定と異なる位置にあるファイルを移動し、移動したファイル数を返す
+/// ファイルはrenameで移動し、`internal_filename`は変更しないため、サーバーの稼働中でも実行できる
+pub async fn reshard_object_files(root: &Path, mut on_progress: impl FnMut()) -> Result<u64, Error> {
+    let mut walker = ObjectFileWalker::new(root);
+    let mut moved = 0;
+    while let Some(path) = walker.next().await? {
+        if reshard_file(root, path).await? {
+            moved += 1;
+        }
+
+        on_progress();
+    }
+
+    remove_empty_shard_dirs(root.to_path_buf(), &nested_storage_paths(root), 0).await?;
+    Ok(moved)
+}
+
+async fn reshard_file(root: &Path, path: PathBuf) -> Result<bool, Error> {
+    let internal_filename = path.file_name().and_then(|name| name.to_str()).unwrap_or_default().to_string();
+    let destination_path = resolve_path(root, internal_filename, false);
+    if destination_path == path {
+        return Ok(false);
+    }
+
+    if destination_path.exists() {
+        tracing::warn!("Skipped {} because {} already exists", path.display(), destination_path.display());
+        return Ok(false);
+    }
+
+    create_parent_dir(&destination_path).await?;
+
+    // 移動する前にサーバーによって削除された場合は無視する
+    if let Err(e) = fs::rename(&path, &destination_path).await {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            return Ok(false);
+        }
+
+        return Err(e.into());
+    }
+
+    tracing::debug!("Moved {} to {}", path.display(), destination_path.display());
+    Ok(true)
+}
+
+/// 一時ファイルとマルチパートのディレクトリ、内側にある別のバケットのディレクトリを除いた、バケット内のファイルを列挙する
+struct ObjectFileWalker {
+    base: PathBuf,
+    excluded: Vec<PathBuf>,
+    directories: Vec<PathBuf>,
+    entries: Option<(PathBuf, fs::ReadDir)>,
+}
+
+impl ObjectFileWalker {
+    fn new(root: &Path) -> Self {
+        let base = root.to_path_buf();
+        Self {
+            directories: vec![base.clone()],
+            excluded: nested_storage_paths(root),
+            base,
+            entries: None,
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<PathBuf>, Error> {
+        loop {
+            if self.entries.is_none() {
+                let Some(directory) = self.directories.pop() else {
+                    return Ok(None);
+                };
+
+                let entries = fs::read_dir(&directory).await?;
+                self.entries = Some((directory, entries));
+            }
+
+            let (directory, entries) = self.entries.as_mut().unwrap();
+            let Some(entry) = entries.next_entry().await? else {
+                self.entries = None;
+                continue;
+            };
+
+            let file_type = entry.file_type().await?;
+            if file_type.is_file() {
+                return Ok(Some(entry.path()));
+            }
+
+            let is_reserved = *directory == self.base && (entry.file_name() == TEMPORARY_DIR || entry.file_name() == MULTIPART_DIR);
+            if file_type.is_dir() && !is_reserved && !self.excluded.contains(&entry.path()) {
+                self.directories.push(entry.path());
+            }
+        }
+    }
+}
+
+/// 現在のシャーディングの階層より深い空のディレクトリを削除する
+/// サーバーが作成する可能性のある階層のディレクトリは、書き込みと競合しないよう残す
+#[async_recursion]
+async fn remove_empty_shard_dirs(directory: PathBuf, excluded: &[PathBuf], depth: usize) -> Result<bool, Error> {
+    let mut is_empty = true;
+    let mut entries = fs::read_dir(&directory).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let is_reserved = depth == 0 && (entry.file_name() == TEMPORARY_DIR || entry.file_name() == MULTIPART_DIR);
+        if is_reserved || excluded.contains(&entry.path()) {
+            is_empty = false;
+            continue;
+        }
+
+        if !entry.file_type().await?.is_dir() || !remove_empty_shard_dirs(entry.path(), excluded, depth + 1).await? {
+            is_empty = false;
+        }
+    }
+
+    if !is_empty || depth <= shard_depth() {
+        return Ok(false);
+    }
+
+    fs::remove_dir(&directory).await?;
+    Ok(true)
+}
+
+/// 一時ファイルを配置先にrenameし、renameがディスクに反映されるよう配置先のディレクトリをfsyncする
+async fn rename_into_place(temporary_path: &Path, path: &Path) -> Result<(), Error> {
+    create_parent_dir(path).await?;
+    fs::rename(temporary_path, path).await?;
+
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        File::open(parent).await?.sync_all().await?;
+    }
+
+    Ok(())
+}
+
+async fn create_parent_dir(path: &Path) -> Result<(), Error> {
+    if let Some(parent) = path.parent() &&
+        !parent.exists()
+    {
+        fs::create_dir_all(parent).await?;
+    }
+
+    Ok(())
+}
+
+fn shard_depth() -> usize {
+    (config::CONFIG.bucket.shard_depth as usize).min(MAX_SHARD_DEPTH)
+}
+
+/// `root`の内側にある、別のバケットのディレクトリを返す
+fn nested_storage_paths(root: &Path) -> Vec<PathBuf> {
+    config::CONFIG
+        .storage_paths()
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path != root && path.starts_with(root))
+        .collect()
+}
+
+/// シャーディングの設定に従って、オブジェクトを書き込むべきパスを返す
+fn resolve_path(base: &Path, internal_path: String, is_multipart: bool) -> PathBuf {
     if is_multipart {
-        base.join(".multipart").join(internal_path)
+        base.join(MULTIPART_DIR).join(internal_path)
     } else {
-        base.join(internal_path)
+        shard_path(base, &internal_path, shard_depth())
     }
 }
+
+/// オブジェクトのファイルが実際に存在するパスを返す
+/// reshardの途中や設定の変更直後でも読めるよう、設定と異なる階層も探す
+fn locate_path(base: &Path, internal_path: &str) -> Option<PathBuf> {
+    let depth = shard_depth();
+    std::iter::once(depth)
+        .chain((0..=MAX_SHARD_DEPTH).filter(|d| *d != depth))
+        .map(|d| shard_path(base, internal_path, d))
+        .find(|path| path.is_file())
+}
+
+/// `abcdef…`を、`depth`が2の場合は`ab/cd/abcdef…`のようなパスにする
+fn shard_path(base: &Path, internal_path: &str, depth: usize) -> PathBuf {
+    if internal_path.len() < depth * SHARD_WIDTH || !internal_path.is_ascii() {
+        return base.join(internal_path);
+    }
+
+    let mut path = base.to_path_buf();
+    for level in 0..depth {
+        path.push(&internal_path[level * SHARD_WIDTH..(level + 1) * SHARD_WIDTH]);
+    }
+
+    path.join(internal_path)
+}
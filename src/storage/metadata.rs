@@ -1,6 +1,11 @@
-use crate::database;
+use crate::{database, server::error::S3Error, storage::multipart};
 use anyhow::Error;
-use sea_orm::{ColumnTrait, EntityTrait, ModelTrait, QueryFilter};
+use sea_orm::{
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, ConnectionTrait, DbBackend, DbErr, EntityTrait, Order, QueryFilter, QueryOrder, QuerySelect, SqlErr, TransactionTrait,
+    sea_query::{Alias, Expr, LikeExpr, SimpleExpr},
+};
 
 pub async fn get_metadata_by_path(path: &str) -> Option<entity::object::Model> {
     let object_data = entity::object::Entity::find()
@@ -16,15 +21,153 @@ pub async fn get_metadata_by_path(path: &str) -> Option<entity::object::Model> {
     object_data.unwrap()
 }
 
-pub async fn create_metadata(model: entity::object::ActiveModel) -> Result<(), Error> {
-    let insert_result = entity::object::Entity::insert(model).exec(database::get_db()).await;
+pub async fn get_metadata_by_paths(paths: &[String]) -> Result<Vec<entity::object::Model>, Error> {
+    let object_data = entity::object::Entity::find()
+        .filter(entity::object::Column::Path.is_in(paths))
+        .all(database::get_db())
+        .await;
 
-    if let Err(e) = insert_result {
-        tracing::error!("Failed to create object metadata: {}", e);
+    if let Err(e) = object_data {
+        tracing::error!("Failed to fetch object metadata for {} paths: {}", paths.len(), e);
         return Err(e.into());
     }
 
-    Ok(())
+    Ok(object_data.unwrap())
+}
+
+pub async fn list_metadata(prefix: &str, after: &str, limit: u64) -> Result<Vec<entity::object::Model>, Error> {
+    let escaped_prefix = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    let list_result = entity::object::Entity::find()
+        .filter(entity::object::Column::Path.like(LikeExpr::new(format!("{escaped_prefix}%")).escape('\\')))
+        .filter(Expr::expr(path_in_byte_order()).gt(after))
+        .order_by(path_in_byte_order(), Order::Asc)
+        .limit(limit)
+        .all(database::get_db())
+        .await;
+
+    if let Err(e) = list_result {
+        tracing::error!("Failed to list object metadata for prefix '{}': {}", prefix, e);
+        return Err(e.into());
+    }
+
+    Ok(list_result.unwrap())
+}
+
+/// S3と同じくパスをバイト順で比較・並べ替えるための式
+/// PostgreSQLはデータベースの照合順序で比較するため、照合順序に"C"を指定する
+fn path_in_byte_order() -> SimpleExpr {
+    match database::get_db().get_database_backend() {
+        DbBackend::Postgres => Expr::cust(r#""path" COLLATE "C""#),
+        _ => Expr::col(entity::object::Column::Path).into(),
+    }
+}
+
+/// オブジェクトの数と、内容のサイズの合計を返す
+pub async fn get_statistics() -> Result<(i64, i64), Error> {
+    // PostgreSQLのSUMはnumericを返すため、bigintにキャストする
+    let statistics = entity::object::Entity::find()
+        .select_only()
+        .column_as(entity::object::Column::Path.count(), "objects")
+        .column_as(
+            Expr::col(entity::object::Column::ContentSize).sum().cast_as(Alias::new("BIGINT")),
+            "stored_bytes",
+        )
+        .into_tuple::<(i64, Option<i64>)>()
+        .one(database::get_db())
+        .await;
+
+    if let Err(e) = statistics {
+        tracing::error!("Failed to aggregate object metadata: {}", e);
+        return Err(e.into());
+    }
+
+    let (objects, stored_bytes) = statistics.unwrap().unwrap_or_default();
+    Ok((objects, stored_bytes.unwrap_or(0)))
+}
+
+/// パスに対応するメタデータを作成し、既に存在する場合は1つのトランザクションで置き換える
+/// 置き換えた場合は置き換える前のメタデータも返す
+/// `upload_id`が指定された場合は同じトランザクションでマルチパートアップロードを削除し、既に削除されていた場合はNoSuchUploadを返す
+pub async fn put_metadata(
+    model: entity::object::ActiveModel,
+    upload_id: Option<&str>,
+) -> Result<(entity::object::Model, Option<entity::object::Model>), Error> {
+    let path = model.path.clone().unwrap();
+    let mut put_result = try_put_metadata(model.clone(), upload_id).await;
+
+    // 存在しないパスに同時に作成された場合は一意制約違反となるため、既存のメタデータを置き換える形で1度だけ再試行する
+    if let Err(e) = &put_result &&
+        matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_)))
+    {
+        tracing::debug!("Object metadata for path '{}' was created concurrently, retrying: {}", path, e);
+        put_result = try_put_metadata(model, upload_id).await;
+    }
+
+    if let Err(e) = put_result {
+        tracing::error!("Failed to put object metadata for path '{}': {}", path, e);
+        return Err(e.into());
+    }
+
+    match put_result.unwrap() {
+        Some(result) => Ok(result),
+        None => Err(S3Error::NoSuchUpload.into()),
+    }
+}
+
+/// `put_metadata`の1回分の試行で、アップロードが既に削除されていた場合はNoneを返す
+async fn try_put_metadata(
+    mut model: entity::object::ActiveModel,
+    upload_id: Option<&str>,
+) -> Result<Option<(entity::object::Model, Option<entity::object::Model>)>, DbErr> {
+    let path = model.path.clone().unwrap();
+    let txn = database::get_db().begin().await?;
+
+    if let Some(upload_id) = upload_id &&
+        multipart::delete_upload_in(&txn, upload_id).await?.is_none()
+    {
+        txn.rollback().await?;
+        return Ok(None);
+    }
+
+    // 置き換える前のメタデータを読み込む前に、既存の行を更新してロックする
+    // SQLiteは`SELECT ... FOR UPDATE`に対応していないため、更新によってデータベースの書き込みロックを取得する
+    entity::object::Entity::update_many()
+        .col_expr(entity::object::Column::Path, Expr::col(entity::object::Column::Path).into())
+        .filter(entity::object::Column::Path.eq(&path))
+        .exec(&txn)
+        .await?;
+
+    let previous = entity::object::Entity::find()
+        .filter(entity::object::Column::Path.eq(&path))
+        .one(&txn)
+        .await?;
+
+    let model = match &previous {
+        Some(previous) => {
+            // 上書きの場合も作成日時は最初に作成された日時を引き継ぐ
+            model.id = Set(previous.id);
+            if let Some(created_at) = previous.created_at {
+                model.created_at = Set(Some(created_at));
+            }
+
+            model.update(&txn).await?
+        }
+        None => model.insert(&txn).await?,
+    };
+
+    txn.commit().await?;
+    Ok(Some((model, previous)))
+}
+
+pub async fn update_metadata(model: entity::object::ActiveModel) -> Result<entity::object::Model, Error> {
+    let update_result = model.update(database::get_db()).await;
+
+    if let Err(e) = update_result {
+        tracing::error!("Failed to update object metadata: {}", e);
+        return Err(e.into());
+    }
+
+    Ok(update_result.unwrap())
 }
 
 #[allow(dead_code)] // TODO: Remove
@@ -42,13 +185,48 @@ pub async fn create_metadata_many(models: Vec<entity::object::ActiveModel>) -> R
     Ok(())
 }
 
-pub async fn delete_metadata(model: entity::object::Model) -> Result<(), Error> {
-    let delete_result = model.delete(database::get_db()).await;
+/// メタデータを削除する
+/// 読み込んだ後に他のリクエストでファイルが置き換えられていた場合は、置き換え後のファイルを解放しないよう削除せずにfalseを返す
+pub async fn delete_metadata(model: &entity::object::Model) -> Result<bool, Error> {
+    let delete_result = delete_unchanged(database::get_db(), model).await;
 
     if let Err(e) = delete_result {
         tracing::error!("Failed to delete object metadata: {}", e);
         return Err(e.into());
     }
 
-    Ok(())
+    Ok(delete_result.unwrap())
+}
+
+/// 複数のメタデータを1つのトランザクションで削除し、削除したメタデータを返す
+/// 読み込んだ後に他のリクエストでファイルが置き換えられていたメタデータは削除しない
+pub async fn delete_metadata_many(models: Vec<entity::object::Model>) -> Result<Vec<entity::object::Model>, Error> {
+    if models.is_empty() {
+        return Ok(models);
+    }
+
+    let txn = database::get_db().begin().await?;
+    let mut deleted = Vec::new();
+    for model in models {
+        if delete_unchanged(&txn, &model).await? {
+            deleted.push(model);
+        }
+    }
+
+    if let Err(e) = txn.commit().await {
+        tracing::error!("Failed to delete multiple object metadata: {}", e);
+        return Err(e.into());
+    }
+
+    Ok(deleted)
+}
+
+async fn delete_unchanged<C: ConnectionTrait>(db: &C, model: &entity::object::Model) -> Result<bool, DbErr> {
+    let delete_result = entity::object::Entity::delete_many()
+        .filter(entity::object::Column::Id.eq(model.id))
+        .filter(entity::object::Column::InternalFilename.eq(&model.internal_filename))
+        .exec(db)
+        .await?;
+
+    Ok(delete_result.rows_affected > 0)
 }
@@ -0,0 +1,185 @@
+use crate::database;
+use anyhow::Error;
+use chrono::Utc;
+use sea_orm::{
+    ActiveValue::Set,
+    ColumnTrait, Condition, DatabaseTransaction, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+    sea_query::{LikeExpr, OnConflict},
+};
+
+pub async fn get_upload(upload_id: &str) -> Option<entity::multipart_upload::Model> {
+    let upload = entity::multipart_upload::Entity::find_by_id(upload_id).one(database::get_db()).await;
+
+    if let Err(e) = upload {
+        tracing::error!("Failed to fetch multipart upload '{}': {}", upload_id, e);
+        return None;
+    }
+
+    upload.unwrap()
+}
+
+pub async fn list_uploads() -> Result<Vec<entity::multipart_upload::Model>, Error> {
+    let uploads = entity::multipart_upload::Entity::find().all(database::get_db()).await;
+
+    if let Err(e) = uploads {
+        tracing::error!("Failed to list multipart uploads: {}", e);
+        return Err(e.into());
+    }
+
+    Ok(uploads.unwrap())
+}
+
+pub async fn count_uploads() -> Result<u64, Error> {
+    let count = entity::multipart_upload::Entity::find().count(database::get_db()).await;
+
+    if let Err(e) = count {
+        tracing::error!("Failed to count multipart uploads: {}", e);
+        return Err(e.into());
+    }
+
+    Ok(count.unwrap())
+}
+
+/// パスが`prefix`で始まるアップロードを、パスとアップロードIDの順に返す
+/// パスが`after_path`と同じアップロードは、`after_upload_id`が指定された場合のみそれより後のものを返す
+pub async fn list_uploads_by_prefix(
+    prefix: &str,
+    after_path: &str,
+    after_upload_id: Option<&str>,
+    limit: u64,
+) -> Result<Vec<entity::multipart_upload::Model>, Error> {
+    let mut after = Condition::any().add(entity::multipart_upload::Column::Path.gt(after_path));
+    if let Some(after_upload_id) = after_upload_id {
+        after = after.add(
+            Condition::all()
+                .add(entity::multipart_upload::Column::Path.eq(after_path))
+                .add(entity::multipart_upload::Column::UploadId.gt(after_upload_id)),
+        );
+    }
+
+    let escaped_prefix = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    let uploads = entity::multipart_upload::Entity::find()
+        .filter(entity::multipart_upload::Column::Path.like(LikeExpr::new(format!("{escaped_prefix}%")).escape('\\')))
+        .filter(after)
+        .order_by_asc(entity::multipart_upload::Column::Path)
+        .order_by_asc(entity::multipart_upload::Column::UploadId)
+        .limit(limit)
+        .all(database::get_db())
+        .await;
+
+    if let Err(e) = uploads {
+        tracing::error!("Failed to list multipart uploads for prefix '{}': {}", prefix, e);
+        return Err(e.into());
+    }
+
+    Ok(uploads.unwrap())
+}
+
+pub async fn list_parts(upload_id: &str) -> Result<Vec<entity::multipart_upload_part::Model>, Error> {
+    let parts = entity::multipart_upload_part::Entity::find()
+        .filter(entity::multipart_upload_part::Column::UploadId.eq(upload_id))
+        .order_by_asc(entity::multipart_upload_part::Column::PartNumber)
+        .all(database::get_db())
+        .await;
+
+    if let Err(e) = parts {
+        tracing::error!("Failed to list parts of multipart upload '{}': {}", upload_id, e);
+        return Err(e.into());
+    }
+
+    Ok(parts.unwrap())
+}
+
+pub async fn create_upload(model: entity::multipart_upload::ActiveModel) -> Result<(), Error> {
+    let insert_result = entity::multipart_upload::Entity::insert(model).exec(database::get_db()).await;
+
+    if let Err(e) = insert_result {
+        tracing::error!("Failed to create multipart upload: {}", e);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+pub async fn touch_upload(upload_id: &str) -> Result<bool, Error> {
+    let update_result = entity::multipart_upload::Entity::update_many()
+        .set(entity::multipart_upload::ActiveModel {
+            last_upload_at: Set(Utc::now().fixed_offset()),
+            ..Default::default()
+        })
+        .filter(entity::multipart_upload::Column::UploadId.eq(upload_id))
+        .exec(database::get_db())
+        .await;
+
+    if let Err(e) = update_result {
+        tracing::error!("Failed to update multipart upload '{}': {}", upload_id, e);
+        return Err(e.into());
+    }
+
+    Ok(update_result.unwrap().rows_affected > 0)
+}
+
+pub async fn upsert_part(model: entity::multipart_upload_part::ActiveModel) -> Result<(), Error> {
+    let insert_result = entity::multipart_upload_part::Entity::insert(model)
+        .on_conflict(
+            OnConflict::columns([
+                entity::multipart_upload_part::Column::UploadId,
+                entity::multipart_upload_part::Column::PartNumber,
+            ])
+            .update_columns([
+                entity::multipart_upload_part::Column::ContentSize,
+                entity::multipart_upload_part::Column::UpdatedAt,
+                entity::multipart_upload_part::Column::ETag,
+            ])
+            .to_owned(),
+        )
+        .exec(database::get_db())
+        .await;
+
+    if let Err(e) = insert_result {
+        tracing::error!("Failed to upsert multipart upload part: {}", e);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// アップロードとそのパートを削除し、削除できたアップロードを返す
+/// 既に他のリクエスト(またはプロセス)によって削除されていた場合はNoneを返す
+pub async fn delete_upload(upload_id: &str) -> Result<Option<entity::multipart_upload::Model>, Error> {
+    let txn = database::get_db().begin().await?;
+
+    let upload = delete_upload_in(&txn, upload_id).await?;
+    if upload.is_none() {
+        txn.rollback().await?;
+        return Ok(None);
+    }
+
+    if let Err(e) = txn.commit().await {
+        tracing::error!("Failed to delete multipart upload '{}': {}", upload_id, e);
+        return Err(e.into());
+    }
+
+    Ok(upload)
+}
+
+/// 呼び出し元のトランザクション内でアップロードとそのパートを削除し、削除できたアップロードを返す
+/// Noneを返した場合、呼び出し元はトランザクションをロールバックする
+pub async fn delete_upload_in(txn: &DatabaseTransaction, upload_id: &str) -> Result<Option<entity::multipart_upload::Model>, DbErr> {
+    let upload = entity::multipart_upload::Entity::find_by_id(upload_id).one(txn).await?;
+    if upload.is_none() {
+        return Ok(None);
+    }
+
+    entity::multipart_upload_part::Entity::delete_many()
+        .filter(entity::multipart_upload_part::Column::UploadId.eq(upload_id))
+        .exec(txn)
+        .await?;
+
+    let delete_result = entity::multipart_upload::Entity::delete_by_id(upload_id).exec(txn).await?;
+    if delete_result.rows_affected == 0 {
+        return Ok(None);
+    }
+
+    Ok(upload)
+}